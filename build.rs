@@ -1,18 +1,68 @@
 use anyhow::*;
 use fs_extra::copy_items;
 use fs_extra::dir::CopyOptions;
-use std::env;
+use std::{env, fmt::Write, fs, path::Path};
+
+/// Glob patterns, one a line relative to res/, choosing what the
+/// `embed-assets` feature embeds. Everything is when there's no manifest.
+const EMBED_MANIFEST: &str = "embed.manifest";
 
 fn main() -> Result<()> {
     // This tells cargo to rerun this script if something in /res/ changes.
     println!("cargo:rerun-if-changed=res/*");
+    println!("cargo:rerun-if-changed={}", EMBED_MANIFEST);
 
+    // Next to the executable, where it's looked for at runtime. OUT_DIR is
+    // target/<profile>/build/<package>-<hash>/out.
     let out_dir = env::var("OUT_DIR")?;
+    let target_dir = Path::new(&out_dir)
+        .ancestors()
+        .nth(3)
+        .context("OUT_DIR isn't in a target directory")?;
     let mut copy_options = CopyOptions::new();
     copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("res/");
-    copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    let paths_to_copy = vec!["res/"];
+    copy_items(&paths_to_copy, target_dir, &copy_options)?;
+
+    if env::var_os("CARGO_FEATURE_EMBED_ASSETS").is_some() {
+        write_embedded(&Path::new(&out_dir).join("embedded.rs"))?;
+    }
+
+    Ok(())
+}
+
+/// Write the table `src/embedded.rs` includes, of each file's path in res/
+/// and its contents.
+fn write_embedded(path: &Path) -> Result<()> {
+    let patterns = match fs::read_to_string(EMBED_MANIFEST) {
+        Result::Ok(manifest) => manifest
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(glob::Pattern::new)
+            .collect::<Result<Vec<_>, _>>()?,
+        Err(_) => vec![glob::Pattern::new("**/*")?],
+    };
 
+    let res = fs::canonicalize("res")?;
+    let mut table = String::from("&[\n");
+    for entry in glob::glob(&format!("{}/**/*", res.display()))? {
+        let file = entry?;
+        if !file.is_file() {
+            continue;
+        }
+        let name = file
+            .strip_prefix(&res)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if patterns.iter().any(|pattern| pattern.matches(&name)) {
+            println!("cargo:rerun-if-changed={}", file.display());
+            writeln!(table, "    ({:?}, include_bytes!({:?})),", name, file)?;
+        }
+    }
+    table.push(']');
+    fs::write(path, table)?;
     Ok(())
 }
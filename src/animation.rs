@@ -0,0 +1,409 @@
+use cgmath::{prelude::*, Matrix4, Quaternion, Vector3};
+
+/// Translation, rotation and scale of a joint relative to its parent.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::zero(),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// Interpolate towards `other` by `t` in [0, 1].
+    pub fn blend(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: slerp(self.rotation, other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+/// Spherical interpolation along the shorter arc.
+fn slerp(a: Quaternion<f32>, b: Quaternion<f32>, t: f32) -> Quaternion<f32> {
+    let b = if a.dot(b) < 0.0 { -b } else { b };
+    a.slerp(b, t)
+}
+
+pub struct Joint {
+    pub parent: Option<usize>,
+    /// The pose the model was modelled in, used where no clip animates it.
+    pub rest: Transform,
+    /// From model space into the joint's space at bind time.
+    pub inverse_bind: Matrix4<f32>,
+    /// Transform of the nodes above a root joint that aren't joints
+    /// themselves, identity for the others.
+    pub base: Matrix4<f32>,
+}
+
+/// Joints ordered so that every parent comes before its children.
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    /// Sort `joints` parents first, remapping their `parent` indices. Also
+    /// returns where each joint ended up, so that data indexed in the
+    /// original order can follow.
+    pub fn new(joints: Vec<Joint>) -> (Self, Vec<usize>) {
+        let mut order = Vec::with_capacity(joints.len());
+        let mut placed = vec![false; joints.len()];
+        while order.len() < joints.len() {
+            let before = order.len();
+            for (i, joint) in joints.iter().enumerate() {
+                if !placed[i] && joint.parent.is_none_or(|parent| placed[parent]) {
+                    placed[i] = true;
+                    order.push(i);
+                }
+            }
+            // A cycle, which a valid glTF can't have. Cut it at the rest.
+            if order.len() == before {
+                order.extend((0..joints.len()).filter(|&i| !placed[i]));
+                break;
+            }
+        }
+
+        let mut new_index = vec![0; joints.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old] = new;
+        }
+        let mut slots = joints.into_iter().map(Some).collect::<Vec<_>>();
+        let joints = order
+            .iter()
+            .map(|&old| {
+                let mut joint = slots[old].take().unwrap();
+                joint.parent = joint
+                    .parent
+                    .map(|parent| new_index[parent])
+                    .filter(|&parent| parent < new_index[old]);
+                joint
+            })
+            .collect();
+        (Self { joints }, new_index)
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    /// The matrices that take bind-pose vertices to `pose`, one per joint.
+    pub fn joint_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        let mut globals: Vec<Matrix4<f32>> = Vec::with_capacity(self.joints.len());
+        for (joint, transform) in self.joints.iter().zip(pose) {
+            let parent = match joint.parent {
+                Some(parent) => globals[parent],
+                None => joint.base,
+            };
+            globals.push(parent * transform.matrix());
+        }
+        globals
+            .iter()
+            .zip(&self.joints)
+            .map(|(global, joint)| global * joint.inverse_bind)
+            .collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+}
+
+#[derive(Clone, Debug)]
+pub enum Keyframes {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+}
+
+/// Keyframes of one property of one joint.
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub joint: usize,
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+    pub interpolation: Interpolation,
+}
+
+impl Channel {
+    /// The keyframes around `time` and how far it is between them.
+    fn locate(&self, time: f32) -> (usize, usize, f32) {
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+        if next == self.times.len() {
+            return (next - 1, next - 1, 0.0);
+        }
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let t = match self.interpolation {
+            Interpolation::Step => 0.0,
+            Interpolation::Linear => (time - start) / (end - start),
+        };
+        (next - 1, next, t)
+    }
+
+    fn apply(&self, time: f32, transform: &mut Transform) {
+        let (a, b, t) = self.locate(time);
+        match &self.keyframes {
+            Keyframes::Translation(values) => transform.translation = values[a].lerp(values[b], t),
+            Keyframes::Rotation(values) => transform.rotation = slerp(values[a], values[b], t),
+            Keyframes::Scale(values) => transform.scale = values[a].lerp(values[b], t),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    /// Overwrite the joints this clip animates with their values at `time`.
+    pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+        for channel in &self.channels {
+            if let Some(transform) = pose.get_mut(channel.joint) {
+                channel.apply(time, transform);
+            }
+        }
+    }
+}
+
+/// A clip being faded out, sampled where it was left.
+struct Fade {
+    clip: usize,
+    time: f32,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// Plays one looping clip at a time, cross-fading when switching clips.
+pub struct AnimationPlayer {
+    pub clip: usize,
+    pub time: f32,
+    pub speed: f32,
+    fade: Option<Fade>,
+}
+
+impl AnimationPlayer {
+    pub fn new(clip: usize) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            fade: None,
+        }
+    }
+
+    /// Switch to `clip`, blending from the current one over `fade` seconds.
+    pub fn play(&mut self, clip: usize, fade: f32) {
+        if clip == self.clip {
+            return;
+        }
+        self.fade = (fade > 0.0).then_some(Fade {
+            clip: self.clip,
+            time: self.time,
+            elapsed: 0.0,
+            duration: fade,
+        });
+        self.clip = clip;
+        self.time = 0.0;
+    }
+
+    /// Advance by `dt` seconds and return the pose of `skeleton`.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        skeleton: &Skeleton,
+        clips: &[AnimationClip],
+    ) -> Vec<Transform> {
+        let mut pose = skeleton.rest_pose();
+        let Some(clip) = clips.get(self.clip) else {
+            return pose;
+        };
+        self.time = advance(self.time, dt * self.speed, clip.duration);
+        clip.sample(self.time, &mut pose);
+
+        if let Some(fade) = &mut self.fade {
+            fade.elapsed += dt;
+            if fade.elapsed >= fade.duration {
+                self.fade = None;
+            } else if let Some(from_clip) = clips.get(fade.clip) {
+                fade.time = advance(fade.time, dt * self.speed, from_clip.duration);
+                let mut from = skeleton.rest_pose();
+                from_clip.sample(fade.time, &mut from);
+                let t = fade.elapsed / fade.duration;
+                for (transform, from) in pose.iter_mut().zip(&from) {
+                    *transform = from.blend(transform, t);
+                }
+            }
+        }
+        pose
+    }
+}
+
+/// Move `time` on by `dt`, looping over `duration`.
+fn advance(time: f32, dt: f32, duration: f32) -> f32 {
+    if duration > 0.0 {
+        (time + dt).rem_euclid(duration)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A joint told apart by its rest translation along x.
+    fn joint(parent: Option<usize>, x: f32) -> Joint {
+        Joint {
+            parent,
+            rest: Transform {
+                translation: Vector3::new(x, 0.0, 0.0),
+                ..Default::default()
+            },
+            inverse_bind: Matrix4::identity(),
+            base: Matrix4::identity(),
+        }
+    }
+
+    fn channel(interpolation: Interpolation) -> Channel {
+        Channel {
+            joint: 0,
+            times: vec![0.0, 1.0, 3.0],
+            keyframes: Keyframes::Translation(vec![
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(2.0, 0.0, 0.0),
+                Vector3::new(6.0, 0.0, 0.0),
+            ]),
+            interpolation,
+        }
+    }
+
+    /// A clip holding joint 0 at `x`.
+    fn hold(x: f32) -> AnimationClip {
+        AnimationClip {
+            name: format!("hold {}", x),
+            duration: 1.0,
+            channels: vec![Channel {
+                joint: 0,
+                times: vec![0.0],
+                keyframes: Keyframes::Translation(vec![Vector3::new(x, 0.0, 0.0)]),
+                interpolation: Interpolation::Linear,
+            }],
+        }
+    }
+
+    #[test]
+    fn parents_first() {
+        let joints = vec![
+            joint(Some(2), 0.0),
+            joint(None, 1.0),
+            joint(Some(1), 2.0),
+            joint(Some(0), 3.0),
+        ];
+        let (skeleton, new_index) = Skeleton::new(joints);
+        assert_eq!(new_index, [2, 0, 1, 3]);
+        let order = skeleton
+            .joints
+            .iter()
+            .map(|joint| joint.rest.translation.x)
+            .collect::<Vec<_>>();
+        assert_eq!(order, [1.0, 2.0, 0.0, 3.0]);
+        let parents = skeleton
+            .joints
+            .iter()
+            .map(|joint| joint.parent)
+            .collect::<Vec<_>>();
+        assert_eq!(parents, [None, Some(0), Some(1), Some(2)]);
+
+        // The leaf sits at the sum of the translations down the chain.
+        let matrices = skeleton.joint_matrices(&skeleton.rest_pose());
+        assert_eq!(matrices[3].w.x, 6.0);
+    }
+
+    #[test]
+    fn cycle_cut() {
+        let (skeleton, new_index) = Skeleton::new(vec![joint(Some(1), 0.0), joint(Some(0), 1.0)]);
+        assert_eq!(new_index, [0, 1]);
+        assert_eq!(skeleton.joints[0].parent, None);
+        assert_eq!(skeleton.joints[1].parent, Some(0));
+        assert_eq!(skeleton.joint_matrices(&skeleton.rest_pose()).len(), 2);
+    }
+
+    #[test]
+    fn step_and_linear() {
+        let linear = channel(Interpolation::Linear);
+        let step = channel(Interpolation::Step);
+        assert_eq!(linear.locate(0.5), (0, 1, 0.5));
+        assert_eq!(linear.locate(2.5), (1, 2, 0.75));
+        assert_eq!(step.locate(0.5), (0, 1, 0.0));
+        assert_eq!(step.locate(2.5), (1, 2, 0.0));
+        // On a keyframe, whichever the interpolation.
+        assert_eq!(linear.locate(1.0), (1, 2, 0.0));
+
+        let mut transform = Transform::default();
+        linear.apply(2.0, &mut transform);
+        assert_eq!(transform.translation.x, 4.0);
+        step.apply(2.0, &mut transform);
+        assert_eq!(transform.translation.x, 2.0);
+    }
+
+    #[test]
+    fn clamped() {
+        for interpolation in [Interpolation::Step, Interpolation::Linear] {
+            let channel = channel(interpolation);
+            assert_eq!(channel.locate(-1.0), (0, 0, 0.0));
+            assert_eq!(channel.locate(3.0), (2, 2, 0.0));
+            assert_eq!(channel.locate(10.0), (2, 2, 0.0));
+
+            let mut transform = Transform::default();
+            channel.apply(10.0, &mut transform);
+            assert_eq!(transform.translation.x, 6.0);
+        }
+    }
+
+    #[test]
+    fn cross_fade() {
+        let (skeleton, _) = Skeleton::new(vec![joint(None, -1.0)]);
+        let clips = [hold(0.0), hold(10.0)];
+        let mut player = AnimationPlayer::new(0);
+        let x = |player: &mut AnimationPlayer, dt| {
+            player.update(dt, &skeleton, &clips)[0].translation.x
+        };
+        assert_eq!(x(&mut player, 0.25), 0.0);
+
+        player.play(1, 1.0);
+        assert_eq!(x(&mut player, 0.25), 2.5);
+        assert_eq!(x(&mut player, 0.25), 5.0);
+        // Playing the clip that's already playing changes nothing.
+        player.play(1, 1.0);
+        assert_eq!(x(&mut player, 0.25), 7.5);
+        assert_eq!(x(&mut player, 0.25), 10.0);
+        assert_eq!(x(&mut player, 0.25), 10.0);
+
+        // Without a fade, the switch is immediate.
+        player.play(0, 0.0);
+        assert_eq!(x(&mut player, 0.25), 0.0);
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    sync::{Arc, Weak},
+};
+
+use crate::{
+    loader::{Job, Loaded, Loader},
+    model, primitives, resources, texture,
+};
+
+/// Refers to an asset in `Assets<T>`, whether it has loaded yet or not. The
+/// asset is kept while any handle to it is.
+pub struct Handle<T> {
+    id: usize,
+    refs: Arc<()>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            refs: self.refs.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.id).finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    Pending,
+    Loaded,
+    /// With the error, for showing in place of the asset.
+    Failed(String),
+}
+
+impl fmt::Display for LoadState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadState::Pending => write!(f, "pending"),
+            LoadState::Loaded => write!(f, "loaded"),
+            LoadState::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+struct Slot<T> {
+    path: String,
+    state: LoadState,
+    /// Shared with whatever was made from it, like the materials of a model
+    /// sharing its textures.
+    asset: Option<Arc<T>>,
+    /// The count of the handles, gone once the last is dropped.
+    refs: Weak<()>,
+}
+
+/// Assets of one type, each loaded once per path.
+pub struct Assets<T> {
+    slots: Vec<Option<Slot<T>>>,
+    ids: HashMap<String, usize>,
+}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Assets<T> {
+    /// A handle to the asset at `path`, which is new when `path` hasn't been
+    /// asked for before and has to be loaded and passed to `finish`. A failed
+    /// load is kept too, so it isn't retried every time.
+    pub fn reserve(&mut self, path: &str) -> (Handle<T>, bool) {
+        if let Some(&id) = self.ids.get(path) {
+            let slot = self.slots[id].as_mut().unwrap();
+            let refs = slot.refs.upgrade().unwrap_or_else(|| {
+                let refs = Arc::new(());
+                slot.refs = Arc::downgrade(&refs);
+                refs
+            });
+            return (Self::handle(id, refs), false);
+        }
+
+        let refs = Arc::new(());
+        let slot = Slot {
+            path: path.to_string(),
+            state: LoadState::Pending,
+            asset: None,
+            refs: Arc::downgrade(&refs),
+        };
+        let id = match self.slots.iter().position(Option::is_none) {
+            Some(id) => {
+                self.slots[id] = Some(slot);
+                id
+            }
+            None => {
+                self.slots.push(Some(slot));
+                self.slots.len() - 1
+            }
+        };
+        self.ids.insert(path.to_string(), id);
+        (Self::handle(id, refs), true)
+    }
+
+    /// Record how loading a handle from `reserve` went.
+    pub fn finish(&mut self, handle: &Handle<T>, result: anyhow::Result<T>) {
+        let slot = self.slot_mut(handle);
+        match result {
+            Ok(asset) => {
+                slot.asset = Some(Arc::new(asset));
+                slot.state = LoadState::Loaded;
+            }
+            Err(error) => {
+                log::warn!("couldn't load {}: {:#}", slot.path, error);
+                slot.asset = None;
+                slot.state = LoadState::Failed(format!("{:#}", error));
+            }
+        }
+    }
+
+    /// Add an asset made in code, under a name like a path. Anything already
+    /// under `name` is replaced, for every handle to it.
+    pub fn add(&mut self, name: &str, asset: T) -> Handle<T> {
+        let (handle, _) = self.reserve(name);
+        self.finish(&handle, Ok(asset));
+        handle
+    }
+
+    /// `None` while the asset is pending or if it failed.
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.slot(handle).asset.as_deref()
+    }
+
+    /// The asset for keeping beyond the handle, as materials keep their
+    /// textures.
+    pub fn get_shared(&self, handle: &Handle<T>) -> Option<Arc<T>> {
+        self.slot(handle).asset.clone()
+    }
+
+    /// `None` also while anything else shares the asset.
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> {
+        self.slot_mut(handle).asset.as_mut().and_then(Arc::get_mut)
+    }
+
+    pub fn state(&self, handle: &Handle<T>) -> &LoadState {
+        &self.slot(handle).state
+    }
+
+    pub fn path(&self, handle: &Handle<T>) -> &str {
+        &self.slot(handle).path
+    }
+
+    /// Drop the assets with no handles left that nothing shares, freeing
+    /// their GPU resources. Returns how many were dropped.
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut dropped = 0;
+        for entry in &mut self.slots {
+            let unused = entry.as_ref().is_some_and(|slot| {
+                slot.refs.strong_count() == 0
+                    && slot
+                        .asset
+                        .as_ref()
+                        .is_none_or(|asset| Arc::strong_count(asset) == 1)
+            });
+            if unused {
+                let slot = entry.take().unwrap();
+                self.ids.remove(&slot.path);
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+
+    fn handle(id: usize, refs: Arc<()>) -> Handle<T> {
+        Handle {
+            id,
+            refs,
+            _marker: PhantomData,
+        }
+    }
+
+    // Slots are only emptied once no handles to them are left, so these
+    // can't fail.
+    fn slot(&self, handle: &Handle<T>) -> &Slot<T> {
+        self.slots[handle.id].as_ref().unwrap()
+    }
+
+    fn slot_mut(&mut self, handle: &Handle<T>) -> &mut Slot<T> {
+        self.slots[handle.id].as_mut().unwrap()
+    }
+}
+
+/// How far along the loads requested from an `AssetServer` are.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Loaded or failed.
+    pub finished: usize,
+    pub failed: usize,
+    /// Grows as models name the textures they need.
+    pub requested: usize,
+}
+
+impl LoadProgress {
+    pub fn is_done(&self) -> bool {
+        self.finished == self.requested
+    }
+}
+
+impl fmt::Display for LoadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{} assets", self.finished, self.requested)?;
+        if self.failed > 0 {
+            write!(f, " ({} failed)", self.failed)?;
+        }
+        Ok(())
+    }
+}
+
+/// A model whose textures are still loading.
+struct WaitingModel {
+    handle: Handle<model::Model>,
+    data: resources::ObjData,
+    textures: Vec<Handle<texture::Texture>>,
+}
+
+/// Every asset loaded from `res`, or made in code and kept alongside. Files
+/// load in the background, streaming in through `update`.
+pub struct AssetServer {
+    pub textures: Assets<texture::Texture>,
+    pub models: Assets<model::Model>,
+    pub materials: Assets<model::Material>,
+    /// What models' materials are made with.
+    pub material_layout: wgpu::BindGroupLayout,
+    /// Drawn in place of models that are still loading or that failed to.
+    placeholder: model::Model,
+    loader: Loader,
+    waiting: Vec<WaitingModel>,
+    progress: LoadProgress,
+    on_progress: Option<Box<dyn FnMut(LoadProgress)>>,
+}
+
+impl AssetServer {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material_layout: wgpu::BindGroupLayout,
+    ) -> anyhow::Result<Self> {
+        let placeholder = model::Model {
+            meshes: vec![primitives::cube(2.0, 1).create_debug_mesh(device, "placeholder", 0)],
+            materials: vec![model::Material::new(
+                device,
+                "placeholder",
+                resources::solid_color_texture(device, queue, [0.5, 0.5, 0.5, 1.0], "placeholder")?,
+                resources::flat_normal_texture(device, queue, "placeholder")?,
+                model::AlphaMode::Opaque,
+                1.0,
+                &material_layout,
+            )],
+        };
+        Ok(Self {
+            textures: Assets::default(),
+            models: Assets::default(),
+            materials: Assets::default(),
+            material_layout,
+            placeholder,
+            loader: Loader::new()?,
+            waiting: Vec::new(),
+            progress: LoadProgress::default(),
+            on_progress: None,
+        })
+    }
+
+    /// Start loading a texture from `res` unless it has been already. A
+    /// normal map is kept apart from the same file as a color texture, which
+    /// is uploaded as sRGB.
+    pub fn request_texture(
+        &mut self,
+        file_name: &str,
+        is_normal_map: bool,
+    ) -> Handle<texture::Texture> {
+        let (handle, new) = self
+            .textures
+            .reserve(&texture_key(file_name, is_normal_map));
+        if new {
+            self.progress.requested += 1;
+            self.loader.spawn(Job::Texture {
+                handle: handle.clone(),
+                path: file_name.to_string(),
+                is_normal_map,
+            });
+        }
+        handle
+    }
+
+    /// Start loading an OBJ model from `res` unless it has been already. It
+    /// is only ready once its textures are, which are shared with
+    /// everything else loaded.
+    pub fn request_model(&mut self, file_name: &str) -> Handle<model::Model> {
+        let (handle, new) = self.models.reserve(file_name);
+        if new {
+            self.progress.requested += 1;
+            self.loader.spawn(Job::Model {
+                handle: handle.clone(),
+                path: file_name.to_string(),
+            });
+        }
+        handle
+    }
+
+    /// The model, or a grey cube until it has loaded or if it failed to.
+    pub fn model(&self, handle: &Handle<model::Model>) -> &model::Model {
+        self.models.get(handle).unwrap_or(&self.placeholder)
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+
+    /// Called from `update` whenever loading gets further.
+    pub fn set_progress_callback(&mut self, callback: impl FnMut(LoadProgress) + 'static) {
+        self.on_progress = Some(Box::new(callback));
+    }
+
+    /// Upload whatever has finished loading, once a frame.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let before = self.progress;
+        for loaded in self.loader.finished() {
+            match loaded {
+                Loaded::Texture {
+                    handle,
+                    is_normal_map,
+                    image,
+                } => {
+                    let label = self.textures.path(&handle);
+                    let texture = image.and_then(|image| {
+                        texture::Texture::from_image(
+                            device,
+                            queue,
+                            &image,
+                            Some(label),
+                            is_normal_map,
+                        )
+                    });
+                    self.finish(texture.is_ok());
+                    self.textures.finish(&handle, texture);
+                }
+                Loaded::Model {
+                    handle,
+                    data: Ok(data),
+                } => {
+                    let mut textures = Vec::new();
+                    for m in &data.materials {
+                        textures.push(self.request_texture(&m.diffuse_texture, false));
+                        if let Some(normal_texture) = &m.normal_texture {
+                            textures.push(self.request_texture(normal_texture, true));
+                        }
+                    }
+                    self.waiting.push(WaitingModel {
+                        handle,
+                        data,
+                        textures,
+                    });
+                }
+                Loaded::Model {
+                    handle,
+                    data: Err(error),
+                } => {
+                    self.finish(false);
+                    self.models.finish(&handle, Err(error));
+                }
+            }
+        }
+
+        for waiting in std::mem::take(&mut self.waiting) {
+            let loading = waiting
+                .textures
+                .iter()
+                .any(|handle| *self.textures.state(handle) == LoadState::Pending);
+            if loading {
+                self.waiting.push(waiting);
+                continue;
+            }
+            let textures = &self.textures;
+            let model = waiting.data.create_model(
+                device,
+                queue,
+                &self.material_layout,
+                |path, is_normal_map| {
+                    let key = texture_key(path, is_normal_map);
+                    let handle = waiting
+                        .textures
+                        .iter()
+                        .find(|handle| textures.path(handle) == key)?;
+                    textures.get_shared(handle)
+                },
+            );
+            self.finish(model.is_ok());
+            self.models.finish(&waiting.handle, model);
+        }
+
+        if self.progress != before {
+            if let Some(on_progress) = &mut self.on_progress {
+                on_progress(self.progress);
+            }
+        }
+    }
+
+    fn finish(&mut self, ok: bool) {
+        self.progress.finished += 1;
+        if !ok {
+            self.progress.failed += 1;
+        }
+    }
+
+    pub fn collect_garbage(&mut self) {
+        // Models first, which may hold the last share of a texture.
+        let dropped = self.models.collect_garbage()
+            + self.materials.collect_garbage()
+            + self.textures.collect_garbage();
+        if dropped > 0 {
+            log::info!("dropped {} unused assets", dropped);
+        }
+    }
+}
+
+/// What a texture is kept under in `AssetServer::textures`.
+fn texture_key(file_name: &str, is_normal_map: bool) -> String {
+    if is_normal_map {
+        format!("{} (normal map)", file_name)
+    } else {
+        file_name.to_string()
+    }
+}
@@ -22,13 +22,16 @@ pub struct CameraBundle {
     pub controller: CameraController,
     pub uniform: CameraUniform,
     pub buffer: wgpu::Buffer,
-    pub layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
     pub mouse_pressed: bool,
 }
 
 impl CameraBundle {
-    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
         //let camera = Camera::new(config.width as f32, config.height as f32);
         //let controller = CameraController::new(0.2);
         let camera = Camera::new((0.0, 5.0, 10.0), cgmath::Deg(-90.0), cgmath::Deg(-20.0));
@@ -41,21 +44,8 @@ impl CameraBundle {
             contents: bytemuck::cast_slice(&[uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
-        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: Some("camera_bind_group_layout"),
-        });
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &layout,
+            layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
                 resource: buffer.as_entire_binding(),
@@ -69,7 +59,6 @@ impl CameraBundle {
             uniform,
             buffer,
             bind_group,
-            layout,
             projection,
             mouse_pressed: false,
         }
@@ -169,8 +158,8 @@ pub struct CameraController {
     rotate_horizontal: f32,
     rotate_vertical: f32,
     scroll: f32,
-    speed: f32,
-    sensitivity: f32,
+    pub speed: f32,
+    pub sensitivity: f32,
 }
 
 impl CameraController {
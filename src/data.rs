@@ -1,193 +1,19 @@
-use crate::model::ModelVertex;
-
 pub const NUM_INSTANCES_PER_ROW: u32 = 9;
 
-pub const VERTICES: &[ModelVertex] = &[
-    // Pentagon
-    ModelVertex {
-        position: [-0.0868241, 0.49240386, 0.0],
-        tex_coords: [0.4131759, 1.0 - 0.99240386],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [-0.49513406, 0.06958647, 0.0],
-        tex_coords: [0.0048659444, 1.0 - 0.56958647],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [-0.21918549, -0.44939706, 0.0],
-        tex_coords: [0.28081453, 1.0 - 0.05060294],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.35966998, -0.3473291, 0.0],
-        tex_coords: [0.85967, 1.0 - 0.1526709],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.44147372, 0.2347359, 0.0],
-        tex_coords: [0.9414737, 1.0 - 0.7347359],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    // Shape
-    ModelVertex {
-        position: [0.0, 0.0, 0.0],
-        tex_coords: [0.4131759, 0.99240386],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.5, 0.2, 0.0],
-        tex_coords: [0.0048659444, 0.56958647],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.5, 0.2, 0.0],
-        tex_coords: [0.28081453, 0.05060294],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.25, 0.4, 0.0],
-        tex_coords: [0.85967, 0.1526709],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.0, 0.6, 0.0],
-        tex_coords: [0.9414737, 0.7347359],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [-0.25, 0.4, 0.0],
-        tex_coords: [0.4131759, 0.99240386],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [-0.5, 0.2, 0.0],
-        tex_coords: [0.0048659444, 0.56958647],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [-0.6, 0.0, 0.0],
-        tex_coords: [0.28081453, 0.05060294],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [-0.5, -0.2, 0.0],
-        tex_coords: [0.85967, 0.1526709],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [-0.25, -0.4, 0.0],
-        tex_coords: [0.9414737, 0.7347359],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.0, -0.6, 0.0],
-        tex_coords: [0.4131759, 0.99240386],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.25, -0.4, 0.0],
-        tex_coords: [0.0048659444, 0.56958647],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.5, -0.2, 0.0],
-        tex_coords: [0.28081453, 0.05060294],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-];
-
-#[rustfmt::skip]
-pub const INDICES: &[u16] = &[
-    // Pentagon
-    0, 1, 4,
-    1, 2, 4,
-    2, 3, 4,
-    // Shape
-    0, 1, 2,
-    0, 2, 3,
-    0, 3, 4,
-    0, 4, 5,
-    0, 5, 6,
-    0, 6, 7,
-    0, 7, 8,
-    0, 8, 9,
-    0, 9, 10,
-    0, 10, 11,
-    0, 11, 12,
-];
-
-pub const DEPTH_VERTICES: &[ModelVertex] = &[
-    ModelVertex {
-        position: [0.50, -1.0, 0.8],
-        tex_coords: [0.0, 1.0],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [1.0, -1.0, 0.8],
-        tex_coords: [1.0, 1.0],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [1.0, -0.25, 0.8],
-        tex_coords: [1.0, 0.0],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-    ModelVertex {
-        position: [0.50, -0.25, 0.8],
-        tex_coords: [0.0, 0.0],
-        normal: [1.0, 1.0, 1.0],
-        tangent: [0.0; 3],
-        bitangent: [0.0; 3],
-    },
-];
-
-#[rustfmt::skip]
-pub const DEPTH_INDICES: &[u16] = &[
-    0, 2, 3,
-    0, 1, 2,
+/// The outline of the alternative shape, fanned out from the origin by
+/// `primitives::fan`.
+pub const SHAPE: &[[f32; 2]] = &[
+    [0.5, 0.2],
+    [0.25, 0.4],
+    [0.0, 0.6],
+    [-0.25, 0.4],
+    [-0.5, 0.2],
+    [-0.6, 0.0],
+    [-0.5, -0.2],
+    [-0.25, -0.4],
+    [0.0, -0.6],
+    [0.25, -0.4],
+    [0.5, -0.2],
 ];
 
 // Old triangle.
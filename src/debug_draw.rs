@@ -0,0 +1,238 @@
+use cgmath::{prelude::*, Matrix4, Point3, Vector3};
+
+use crate::{reflect::Reflection, render, shader::Shader, texture::Texture};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+impl LineVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+pub const RED: [f32; 3] = [1.0, 0.0, 0.0];
+pub const GREEN: [f32; 3] = [0.0, 1.0, 0.0];
+pub const BLUE: [f32; 3] = [0.0, 0.0, 1.0];
+
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Immediate-mode debug lines.
+///
+/// Shapes are collected over a frame and drawn by `render` on top of the main
+/// pass, which then starts the next batch. The camera bind group goes in
+/// group 0.
+pub struct DebugDraw {
+    vertices: Vec<LineVertex>,
+    buffer: wgpu::Buffer,
+    /// Size of `buffer` in vertices.
+    capacity: usize,
+    depth_tested_pipeline: wgpu::RenderPipeline,
+    overlay_pipeline: wgpu::RenderPipeline,
+    /// Hide lines behind the scene, using the depth left by the main pass.
+    pub depth_test: bool,
+}
+
+impl DebugDraw {
+    /// Reflected along with the other shaders so that it can share their
+    /// camera bind group.
+    pub fn shader() -> Shader<'static> {
+        Shader::new(Some("debug_draw.shader"), include_str!("debug_draw.wgsl"))
+            .define_value("CAMERA_GROUP", 0)
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        reflection: &Reflection,
+    ) -> anyhow::Result<Self> {
+        let shader = Self::shader();
+        let layout = reflection.create_pipeline_layout(
+            device,
+            &shader,
+            Some("debug_draw.pipeline_layout"),
+        )?;
+        let pipeline = |depth_compare, label| {
+            render::create_render_pipeline(
+                device,
+                &layout,
+                config.format,
+                Some(Texture::DEPTH_FORMAT),
+                &[LineVertex::desc()],
+                &shader,
+                &render::PipelineOptions {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    depth_compare,
+                    depth_write_enabled: false,
+                    ..Default::default()
+                },
+                Some(label),
+            )
+        };
+        let depth_tested_pipeline =
+            pipeline(wgpu::CompareFunction::LessEqual, "debug_draw.depth_tested");
+        let overlay_pipeline = pipeline(wgpu::CompareFunction::Always, "debug_draw.overlay");
+
+        let capacity = 1024;
+        Ok(Self {
+            vertices: Vec::new(),
+            buffer: create_buffer(device, capacity),
+            capacity,
+            depth_tested_pipeline,
+            overlay_pipeline,
+            depth_test: true,
+        })
+    }
+
+    /// Forget the last frame's lines. Called every frame before anything is
+    /// drawn, whether or not the last frame made it to `render`.
+    pub fn begin_frame(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn line(&mut self, a: Point3<f32>, b: Point3<f32>, color: [f32; 3]) {
+        self.vertices.push(LineVertex {
+            position: a.into(),
+            color,
+        });
+        self.vertices.push(LineVertex {
+            position: b.into(),
+            color,
+        });
+    }
+
+    /// An axis-aligned box.
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 3]) {
+        let corner = |i: usize| {
+            Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        self.box_edges(corner, color);
+    }
+
+    /// Three circles around the axes through `center`.
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: [f32; 3]) {
+        for (u, v) in [
+            (Vector3::unit_x(), Vector3::unit_y()),
+            (Vector3::unit_y(), Vector3::unit_z()),
+            (Vector3::unit_z(), Vector3::unit_x()),
+        ] {
+            let point = |i: usize| {
+                let angle = std::f32::consts::TAU * i as f32 / CIRCLE_SEGMENTS as f32;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for i in 0..CIRCLE_SEGMENTS {
+                self.line(point(i), point(i + 1), color);
+            }
+        }
+    }
+
+    /// X, Y and Z in red, green and blue.
+    pub fn axes(&mut self, origin: Point3<f32>, size: f32) {
+        self.line(origin, origin + Vector3::unit_x() * size, RED);
+        self.line(origin, origin + Vector3::unit_y() * size, GREEN);
+        self.line(origin, origin + Vector3::unit_z() * size, BLUE);
+    }
+
+    /// The volume seen through `view_proj`, such as `Projection::calc_matrix()
+    /// * Camera::calc_matrix()`.
+    pub fn frustum(&mut self, view_proj: Matrix4<f32>, color: [f32; 3]) {
+        let Some(inverse) = view_proj.invert() else {
+            return;
+        };
+        // wgpu clip space has depth from 0 to 1.
+        let corner = |i: usize| {
+            let ndc = cgmath::Vector4::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+                1.0,
+            );
+            let world = inverse * ndc;
+            Point3::from_homogeneous(world)
+        };
+        self.box_edges(corner, color);
+    }
+
+    /// The 12 edges between 8 corners indexed by their x, y and z bits.
+    fn box_edges(&mut self, corner: impl Fn(usize) -> Point3<f32>, color: [f32; 3]) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    /// Draw the lines collected since `begin_frame`.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        depth: &Texture,
+        camera_bind_group: &wgpu::BindGroup,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.buffer = create_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug_draw.render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(if self.depth_test {
+            &self.depth_tested_pipeline
+        } else {
+            &self.overlay_pipeline
+        });
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.draw(0..self.vertices.len() as u32, 0..1);
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("debug_draw.buffer"),
+        size: (capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use crate::{
+    model::{self, Vertex},
+    reflect::Reflection,
+    render,
+    shader::Shader,
+    texture::Texture,
+    vertex::InstanceRaw,
+};
+
+/// What the model pass shows, cycled with `V`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    Shaded,
+    /// Shaded with the triangle edges drawn on top.
+    Wireframe,
+    Normals,
+    Tangents,
+    Bitangents,
+    TexCoords,
+    /// The normal map texels before they are moved into tangent space.
+    NormalMap,
+    MipLevel,
+}
+
+/// Views that replace the shaded pass, with their `debug_view.wgsl` define
+/// and pipeline label.
+const VIEWS: &[(DebugView, &str, &str)] = &[
+    (DebugView::Normals, "VIEW_NORMALS", "debug_view.normals"),
+    (DebugView::Tangents, "VIEW_TANGENTS", "debug_view.tangents"),
+    (
+        DebugView::Bitangents,
+        "VIEW_BITANGENTS",
+        "debug_view.bitangents",
+    ),
+    (
+        DebugView::TexCoords,
+        "VIEW_TEX_COORDS",
+        "debug_view.tex_coords",
+    ),
+    (
+        DebugView::NormalMap,
+        "VIEW_NORMAL_MAP",
+        "debug_view.normal_map",
+    ),
+    (
+        DebugView::MipLevel,
+        "VIEW_MIP_LEVEL",
+        "debug_view.mip_level",
+    ),
+];
+const WIREFRAME_LABEL: &str = "debug_view.wireframe";
+
+impl DebugView {
+    const ALL: [Self; 8] = [
+        Self::Shaded,
+        Self::Wireframe,
+        Self::Normals,
+        Self::Tangents,
+        Self::Bitangents,
+        Self::TexCoords,
+        Self::NormalMap,
+        Self::MipLevel,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|view| *view == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Whether the model is drawn by a debug pipeline instead of the usual one.
+    pub fn replaces_shading(self) -> bool {
+        VIEWS.iter().any(|(view, _, _)| *view == self)
+    }
+}
+
+/// Pipeline variants of `debug_view.wgsl` for each `DebugView`.
+///
+/// They use the material pipeline's bind groups: the material at group 0,
+/// camera at 1 and rotation at 2.
+pub struct DebugViews {
+    pipelines: Vec<(DebugView, wgpu::RenderPipeline)>,
+    wireframe_pipeline: wgpu::RenderPipeline,
+    /// Whether the wireframe uses `PolygonMode::Line` rather than the
+    /// barycentric fallback over `Mesh::unindexed_buffer`.
+    line_mode: bool,
+}
+
+impl DebugViews {
+    /// The shader variants, to be reflected along with the material shader
+    /// so that they share its bind group layouts.
+    pub fn shaders(device: &wgpu::Device) -> Vec<Shader<'static>> {
+        let wireframe = if line_mode(device) {
+            "VIEW_WIREFRAME"
+        } else {
+            "VIEW_BARYCENTRIC"
+        };
+        VIEWS
+            .iter()
+            .map(|(_, define, label)| shader(label, define))
+            .chain(std::iter::once(shader(WIREFRAME_LABEL, wireframe)))
+            .collect()
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        reflection: &Reflection,
+    ) -> anyhow::Result<Self> {
+        let line_mode = line_mode(device);
+        let shaders = Self::shaders(device);
+        let pipeline =
+            |shader: &Shader<'static>, options: &render::PipelineOptions| -> anyhow::Result<_> {
+                Ok(render::create_render_pipeline(
+                    device,
+                    &reflection.create_pipeline_layout(device, shader, shader.label())?,
+                    config.format,
+                    Some(Texture::DEPTH_FORMAT),
+                    &[model::ModelVertex::desc(), InstanceRaw::desc()],
+                    shader,
+                    options,
+                    shader.label(),
+                ))
+            };
+
+        let pipelines = VIEWS
+            .iter()
+            .zip(&shaders)
+            .map(|((view, _, _), shader)| {
+                Ok((
+                    *view,
+                    pipeline(shader, &render::PipelineOptions::default())?,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Drawn over the shaded model, so it must pass where that left its depth.
+        let wireframe_pipeline = pipeline(
+            shaders.last().unwrap(),
+            &render::PipelineOptions {
+                polygon_mode: if line_mode {
+                    wgpu::PolygonMode::Line
+                } else {
+                    wgpu::PolygonMode::Fill
+                },
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                depth_write_enabled: false,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            pipelines,
+            wireframe_pipeline,
+            line_mode,
+        })
+    }
+
+    /// Draw the part of `view` that goes into the model pass. Nothing is
+    /// drawn for `DebugView::Shaded`, and only the edges for
+    /// `DebugView::Wireframe`, so the regular draw has to come first.
+    pub fn draw<'a>(
+        &'a self,
+        view: DebugView,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        model: &'a model::Model,
+        instances: Range<u32>,
+    ) {
+        let pipeline = match view {
+            DebugView::Shaded => return,
+            DebugView::Wireframe => &self.wireframe_pipeline,
+            _ => match self.pipelines.iter().find(|(v, _)| *v == view) {
+                Some((_, pipeline)) => pipeline,
+                None => return,
+            },
+        };
+        render_pass.set_pipeline(pipeline);
+
+        for mesh in &model.meshes {
+            render_pass.set_bind_group(0, &model.materials[mesh.material].bind_group, &[]);
+            if view == DebugView::Wireframe && !self.line_mode {
+                if let Some(buffer) = &mesh.unindexed_buffer {
+                    render_pass.set_vertex_buffer(0, buffer.slice(..));
+                    render_pass.draw(0..mesh.num_elements, instances.clone());
+                }
+            } else {
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+                render_pass.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+            }
+        }
+    }
+}
+
+fn line_mode(device: &wgpu::Device) -> bool {
+    device
+        .features()
+        .contains(wgpu::Features::POLYGON_MODE_LINE)
+}
+
+fn shader(label: &'static str, define: &str) -> Shader<'static> {
+    Shader::new(Some(label), include_str!("debug_view.wgsl"))
+        .define(define)
+        .define("TANGENTS")
+        .define_value("CAMERA_GROUP", 1)
+        .define_value("ROTATION_GROUP", 2)
+}
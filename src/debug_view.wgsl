@@ -0,0 +1,111 @@
+// Debug views of the material pipeline's inputs. One VIEW_* define selects
+// what `fs_main` shows.
+#include "camera.wgsl"
+#include "rotation.wgsl"
+#include "vertex_input.wgsl"
+#include "instance_input.wgsl"
+#include "material.wgsl"
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_tangent: vec3<f32>,
+    @location(3) world_bitangent: vec3<f32>,
+    @location(4) barycentric: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    ) * rotation.view_proj;
+
+    let rotation_3x3 = mat3x3<f32>(
+        rotation.view_proj[0].xyz,
+        rotation.view_proj[1].xyz,
+        rotation.view_proj[2].xyz,
+    );
+
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    ) * rotation_3x3;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.tex_coords = model.tex_coords;
+    out.world_normal = normalize(normal_matrix * model.normal);
+    out.world_tangent = normalize(normal_matrix * model.tangent.xyz);
+    out.world_bitangent = cross(out.world_normal, out.world_tangent) * model.tangent.w;
+    // Only meaningful for non-indexed draws, see `model::Mesh::unindexed_buffer`.
+    let corner = index % 3u;
+    out.barycentric = vec3<f32>(
+        f32(corner == 0u),
+        f32(corner == 1u),
+        f32(corner == 2u),
+    );
+    return out;
+}
+
+let WIREFRAME_COLOR: vec3<f32> = vec3<f32>(0.0, 1.0, 0.3);
+
+// Show a unit vector with each axis mapped from [-1, 1] to [0, 1].
+fn show_direction(v: vec3<f32>) -> vec4<f32> {
+    return vec4<f32>(normalize(v) * 0.5 + 0.5, 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+#ifdef VIEW_WIREFRAME
+    return vec4<f32>(WIREFRAME_COLOR, 1.0);
+#endif
+#ifdef VIEW_BARYCENTRIC
+    // Keep a roughly one pixel band along each edge.
+    let width = fwidth(in.barycentric);
+    let edge = smoothstep(vec3<f32>(0.0), width * 1.5, in.barycentric);
+    if (min(edge.x, min(edge.y, edge.z)) > 0.5) {
+        discard;
+    }
+    return vec4<f32>(WIREFRAME_COLOR, 1.0);
+#endif
+#ifdef VIEW_NORMALS
+    return show_direction(in.world_normal);
+#endif
+#ifdef VIEW_TANGENTS
+    return show_direction(in.world_tangent);
+#endif
+#ifdef VIEW_BITANGENTS
+    return show_direction(in.world_bitangent);
+#endif
+#ifdef VIEW_TEX_COORDS
+    return vec4<f32>(fract(in.tex_coords), 0.0, 1.0);
+#endif
+#ifdef VIEW_NORMAL_MAP
+    return vec4<f32>(textureSample(t_normal, s_normal, in.tex_coords).rgb, 1.0);
+#endif
+#ifdef VIEW_MIP_LEVEL
+    // The level a trilinear lookup of `t_diffuse` would pick, from blue at
+    // level 0 through green and yellow to red at level 4 and above.
+    let texels = in.tex_coords * vec2<f32>(textureDimensions(t_diffuse));
+    let dx = dpdx(texels);
+    let dy = dpdy(texels);
+    let level = max(0.5 * log2(max(dot(dx, dx), dot(dy, dy))), 0.0);
+    let t = clamp(level / 4.0, 0.0, 1.0);
+    let color = mix(
+        mix(vec3<f32>(0.0, 0.0, 1.0), vec3<f32>(0.0, 1.0, 0.0), clamp(t * 2.0, 0.0, 1.0)),
+        mix(vec3<f32>(1.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), clamp(t * 2.0 - 1.0, 0.0, 1.0)),
+        step(0.5, t)
+    );
+    let base = textureSample(t_diffuse, s_diffuse, in.tex_coords).rgb;
+    return vec4<f32>(mix(base, color, 0.6), 1.0);
+#endif
+}
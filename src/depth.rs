@@ -1,75 +1,194 @@
-use wgpu::util::DeviceExt;
+use crate::{
+    camera::Projection, reflect::Reflection, render::RenderPass, shader::Shader, texture::Texture,
+};
 
-use crate::model::{ModelVertex, Vertex};
-use crate::vertex;
-use crate::{data::DEPTH_INDICES, data::DEPTH_VERTICES, render::RenderPass, texture::Texture};
+/// Texels in the colormap lookup texture.
+const COLORMAP_SIZE: u32 = 256;
 
+/// How the depth overlay colors depths, from near to far.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Turbo,
+    Grayscale,
+}
+
+impl Colormap {
+    pub const ALL: [Self; 3] = [Self::Viridis, Self::Turbo, Self::Grayscale];
+
+    /// One texel for each of `COLORMAP_SIZE` points along the colormap.
+    fn texels(self) -> Vec<u8> {
+        let (gradient, reversed) = match self {
+            Colormap::Viridis => (colorgrad::viridis(), false),
+            Colormap::Turbo => (colorgrad::turbo(), false),
+            // Black near and white far.
+            Colormap::Grayscale => (colorgrad::greys(), true),
+        };
+        (0..COLORMAP_SIZE)
+            .flat_map(|i| {
+                let t = i as f64 / (COLORMAP_SIZE - 1) as f64;
+                gradient.at(if reversed { 1.0 - t } else { t }).to_rgba8()
+            })
+            .collect()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OverlayUniform {
+    rect: [f32; 4],
+    znear: f32,
+    zfar: f32,
+    min_depth: f32,
+    max_depth: f32,
+}
+
+/// Shows the depth buffer in a corner of the screen, linearized and colored
+/// by a colormap.
 pub struct DepthPass {
     pub texture: Texture,
     pub layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
-    pub vertex_buffer: wgpu::Buffer,
-    pub index_buffer: wgpu::Buffer,
     pub render_pipeline: wgpu::RenderPipeline,
-    pub gradient: vertex::GradientSource,
+    /// Left and top of the overlay, as fractions of the screen.
+    pub position: [f32; 2],
+    /// Width and height of the overlay, as fractions of the screen.
+    pub size: [f32; 2],
+    /// The distances from the camera at either end of the colormap.
+    pub range: [f32; 2],
+    pub colormap: Colormap,
+    /// What's in `colormap_texture`, to know when it has to be rewritten.
+    shown_colormap: Colormap,
+    colormap_texture: Texture,
+    depth_sampler: wgpu::Sampler,
+    clip_planes: [f32; 2],
+    uniform_buffer: wgpu::Buffer,
 }
 
-impl RenderPass for DepthPass {
-    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
-        let texture = Texture::create_depth_texture(device, config, "depth_pass");
-        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("depth_pass.bind_group"),
+impl DepthPass {
+    /// Linearize with `projection`'s clipping planes, which the depth buffer
+    /// was rendered with.
+    pub fn set_projection(&mut self, projection: &Projection) {
+        self.clip_planes = [projection.znear, projection.zfar];
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &Texture,
+        depth_sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+        colormap_texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
             entries: &[
-                wgpu::BindGroupLayoutEntry {
+                wgpu::BindGroupEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Depth,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
                 },
-                wgpu::BindGroupLayoutEntry {
+                wgpu::BindGroupEntry {
                     binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
-                    count: None,
+                    resource: wgpu::BindingResource::Sampler(depth_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&colormap_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&colormap_texture.sampler),
                 },
             ],
-        });
-        let bind_group = texture.create_bind_group(device, &layout, Some("depth_bind_group"));
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("depth_pass.index_buffer"),
-            contents: bytemuck::cast_slice(DEPTH_VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("depth_pass.index_buffer"),
-            contents: bytemuck::cast_slice(DEPTH_INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
+            label: Some("depth_pass.bind_group"),
+        })
+    }
+}
 
-        let gradient = vertex::GradientSource::new(device);
+impl RenderPass for DepthPass {
+    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let texture = Texture::create_depth_texture(device, config, "depth_pass");
+        let shader = Shader::new(Some("depth_pass.shader"), include_str!("shader_depth.wgsl"));
+        let reflection = Reflection::new(&[&shader]).unwrap();
+        reflection
+            .check_sizes(&[("overlay", std::mem::size_of::<OverlayUniform>())])
+            .unwrap();
+        let layout = reflection
+            .create_bind_group_layout(
+                device,
+                &["t_depth", "s_depth", "overlay", "t_colormap", "s_colormap"],
+                Some("depth_pass.bind_group_layout"),
+            )
+            .unwrap();
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("depth_pass.pipe_line_layout"),
-            bind_group_layouts: &[&layout, &gradient.layout],
-            //bind_group_layouts: &[&layout],
-            push_constant_ranges: &[],
+        // The depth texture's own sampler compares, but the raw depth is
+        // wanted here.
+        let depth_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("depth_pass.depth_sampler"),
+            ..Default::default()
         });
-
-        let shader_depth = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("depth_pass.shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader_depth.wgsl").into()),
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("depth_pass.uniform_buffer"),
+            size: std::mem::size_of::<OverlayUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
         });
+        // Written by `update`.
+        let colormap_texture = {
+            let size = wgpu::Extent3d {
+                width: COLORMAP_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            };
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("depth_pass.colormap"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            });
+            Texture {
+                texture,
+                view,
+                sampler,
+            }
+        };
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &texture,
+            &depth_sampler,
+            &uniform_buffer,
+            &colormap_texture,
+        );
+
+        let pipeline_layout = reflection
+            .create_pipeline_layout(device, &shader, Some("depth_pass.pipe_line_layout"))
+            .unwrap();
+
+        let shader_depth = shader.create_module(device).unwrap();
         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("depth_pass.render_pipeline"),
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader_depth,
                 entry_point: "vs_main",
-                buffers: &[ModelVertex::desc()],
+                buffers: &[],
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader_depth,
@@ -84,7 +203,7 @@ impl RenderPass for DepthPass {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                cull_mode: None,
                 // Setting this to anything other than Fill requires
                 // Features::NON_FILL_POLYGON_MODE
                 polygon_mode: wgpu::PolygonMode::Fill,
@@ -106,23 +225,71 @@ impl RenderPass for DepthPass {
             texture,
             layout,
             bind_group,
-            vertex_buffer,
-            index_buffer,
             render_pipeline,
-            gradient,
+            // The bottom right corner.
+            position: [0.75, 0.625],
+            size: [0.25, 0.375],
+            range: [0.1, 50.0],
+            colormap: Colormap::default(),
+            // Anything but `colormap`, so that the first `update` writes it.
+            shown_colormap: Colormap::Grayscale,
+            colormap_texture,
+            depth_sampler,
+            clip_planes: [0.1, 100.0],
+            uniform_buffer,
         }
     }
 
     fn update(&mut self, queue: &wgpu::Queue) {
-        self.gradient.update(queue);
+        if self.colormap != self.shown_colormap {
+            self.shown_colormap = self.colormap;
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &self.colormap_texture.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &self.colormap.texels(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * COLORMAP_SIZE),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: COLORMAP_SIZE,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let [znear, zfar] = self.clip_planes;
+        let uniform = OverlayUniform {
+            rect: [
+                self.position[0],
+                self.position[1],
+                self.size[0],
+                self.size[1],
+            ],
+            znear,
+            zfar,
+            min_depth: self.range[0],
+            // Kept apart so that the colormap doesn't divide by zero.
+            max_depth: self.range[1].max(self.range[0] + 1e-3),
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
     }
 
     fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
         self.texture = Texture::create_depth_texture(device, config, "depth_pass.texture_resized");
-        self.bind_group = self.texture.create_bind_group(
+        self.bind_group = Self::create_bind_group(
             device,
             &self.layout,
-            Some("depth_pass.bind_group_resized"),
+            &self.texture,
+            &self.depth_sampler,
+            &self.uniform_buffer,
+            &self.colormap_texture,
         );
     }
 
@@ -141,9 +308,6 @@ impl RenderPass for DepthPass {
         });
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.set_bind_group(1, &self.gradient.bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(0..(DEPTH_INDICES.len() as u32), 0, 0..1);
+        render_pass.draw(0..6, 0..1);
     }
 }
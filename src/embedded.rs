@@ -0,0 +1,22 @@
+/// Files built into the binary, by their paths in `res`.
+#[cfg(feature = "embed-assets")]
+const FILES: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/embedded.rs"));
+
+/// Without the `embed-assets` feature, just enough to show what went wrong
+/// when nothing else is found.
+#[cfg(not(feature = "embed-assets"))]
+const FILES: &[(&str, &[u8])] = &[(
+    "DejaVuSansMono.ttf",
+    include_bytes!("../res/DejaVuSansMono.ttf"),
+)];
+
+/// Whether everything is embedded, to be served before anything else rather
+/// than as a last resort.
+pub const ALL: bool = cfg!(feature = "embed-assets");
+
+pub fn get(file_name: &str) -> Option<&'static [u8]> {
+    FILES
+        .iter()
+        .find(|(name, _)| *name == file_name)
+        .map(|(_, data)| *data)
+}
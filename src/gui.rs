@@ -0,0 +1,533 @@
+use std::{collections::HashMap, ops::Range};
+
+use instant::Instant;
+use wgpu::util::DeviceExt;
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+use crate::{reflect::Reflection, render, shader::Shader};
+
+/// Points scrolled per line of a mouse wheel.
+const SCROLL_LINE: f32 = 50.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GuiUniform {
+    screen_size: [f32; 2],
+}
+
+/// Layout of `egui::epaint::Vertex`.
+const ATTRIBS: [wgpu::VertexAttribute; 3] =
+    wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4];
+
+fn vertex_desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<egui::epaint::Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &ATTRIBS,
+    }
+}
+
+/// A texture egui asked for, such as its font atlas.
+struct GuiTexture {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// One egui mesh within the shared vertex and index buffers.
+struct DrawCall {
+    clip_rect: egui::Rect,
+    texture_id: egui::TextureId,
+    indices: Range<u32>,
+    base_vertex: i32,
+}
+
+/// An egui context fed from winit events and drawn with wgpu over
+/// everything else.
+///
+/// Events go through `handle_event` first, which keeps those that land on a
+/// window or a focused text field so that they don't also move the camera.
+/// `run` lays out the frame and `render` draws it.
+pub struct Gui {
+    context: egui::Context,
+    input: egui::RawInput,
+    modifiers: egui::Modifiers,
+    pointer: egui::Pos2,
+    pixels_per_point: f32,
+    start: Instant,
+    pub visible: bool,
+
+    uniform: GuiUniform,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    texture_layout: wgpu::BindGroupLayout,
+    render_pipeline: wgpu::RenderPipeline,
+    /// Surface size in pixels.
+    size: [u32; 2],
+
+    textures: HashMap<egui::TextureId, GuiTexture>,
+    /// Texture changes from `run` waiting for `render`.
+    textures_delta: egui::TexturesDelta,
+    vertices: Vec<egui::epaint::Vertex>,
+    indices: Vec<u32>,
+    draw_calls: Vec<DrawCall>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    /// Sizes of `vertex_buffer` and `index_buffer` in elements.
+    capacity: [usize; 2],
+}
+
+impl Gui {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        pixels_per_point: f32,
+    ) -> anyhow::Result<Self> {
+        let shader = Shader::new(Some("gui.shader"), include_str!("gui.wgsl"));
+        let reflection = Reflection::new(&[&shader])?;
+        reflection.check_sizes(&[("gui", std::mem::size_of::<GuiUniform>())])?;
+
+        let uniform = GuiUniform {
+            screen_size: [
+                config.width as f32 / pixels_per_point,
+                config.height as f32 / pixels_per_point,
+            ],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("gui.uniform_buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &reflection.create_bind_group_layout(
+                device,
+                &["gui"],
+                Some("gui.uniform_bind_group_layout"),
+            )?,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("gui.uniform_bind_group"),
+        });
+        let texture_layout = reflection.create_bind_group_layout(
+            device,
+            &["t_gui", "s_gui"],
+            Some("gui.texture_bind_group_layout"),
+        )?;
+
+        // egui colors are premultiplied.
+        let render_pipeline = render::create_render_pipeline(
+            device,
+            &reflection.create_pipeline_layout(device, &shader, Some("gui.pipeline_layout"))?,
+            config.format,
+            None,
+            &[vertex_desc()],
+            &shader,
+            &render::PipelineOptions {
+                cull_mode: None,
+                blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                ..Default::default()
+            },
+            Some("gui.render_pipeline"),
+        );
+
+        let capacity = [4096, 8192];
+        Ok(Self {
+            context: egui::Context::default(),
+            input: egui::RawInput::default(),
+            modifiers: egui::Modifiers::default(),
+            pointer: egui::Pos2::ZERO,
+            pixels_per_point,
+            start: Instant::now(),
+            visible: true,
+            uniform,
+            uniform_buffer,
+            uniform_bind_group,
+            texture_layout,
+            render_pipeline,
+            size: [config.width, config.height],
+            textures: HashMap::new(),
+            textures_delta: egui::TexturesDelta::default(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            draw_calls: Vec::new(),
+            vertex_buffer: create_vertex_buffer(device, capacity[0]),
+            index_buffer: create_index_buffer(device, capacity[1]),
+            capacity,
+        })
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, config: &wgpu::SurfaceConfiguration) {
+        self.size = [config.width, config.height];
+        self.write_uniform(queue);
+    }
+
+    fn write_uniform(&mut self, queue: &wgpu::Queue) {
+        self.uniform.screen_size = [
+            self.size[0] as f32 / self.pixels_per_point,
+            self.size[1] as f32 / self.pixels_per_point,
+        ];
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    /// Pass `event` on to egui, returning whether egui used it.
+    ///
+    /// Only presses are kept from the rest of the app: releases always go
+    /// through so that nothing is left held down.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::ScaleFactorChanged { scale_factor, .. } = event {
+            self.pixels_per_point = *scale_factor as f32;
+        }
+        if !self.visible {
+            return false;
+        }
+
+        match event {
+            WindowEvent::ModifiersChanged(state) => {
+                self.modifiers = egui::Modifiers {
+                    alt: state.alt(),
+                    ctrl: state.ctrl(),
+                    shift: state.shift(),
+                    mac_cmd: cfg!(target_os = "macos") && state.logo(),
+                    command: if cfg!(target_os = "macos") {
+                        state.logo()
+                    } else {
+                        state.ctrl()
+                    },
+                };
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer = egui::pos2(
+                    position.x as f32 / self.pixels_per_point,
+                    position.y as f32 / self.pixels_per_point,
+                );
+                self.input
+                    .events
+                    .push(egui::Event::PointerMoved(self.pointer));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.input.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+                let pressed = *state == ElementState::Pressed;
+                self.input.events.push(egui::Event::PointerButton {
+                    pos: self.pointer,
+                    button,
+                    pressed,
+                    modifiers: self.modifiers,
+                });
+                pressed && self.context.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => egui::vec2(*x, *y) * SCROLL_LINE,
+                    MouseScrollDelta::PixelDelta(delta) => {
+                        egui::vec2(delta.x as f32, delta.y as f32) / self.pixels_per_point
+                    }
+                };
+                self.input.events.push(egui::Event::Scroll(delta));
+                self.context.wants_pointer_input()
+            }
+            WindowEvent::ReceivedCharacter(c) => {
+                if c.is_control() {
+                    return false;
+                }
+                self.input.events.push(egui::Event::Text(c.to_string()));
+                self.context.wants_keyboard_input()
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                if let Some(key) = translate_key(*key) {
+                    self.input.events.push(egui::Event::Key {
+                        key,
+                        pressed,
+                        modifiers: self.modifiers,
+                    });
+                }
+                pressed && self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    /// Lay out a frame with `run_ui` and tessellate it for `render`.
+    pub fn run(&mut self, run_ui: impl FnOnce(&egui::Context)) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.draw_calls.clear();
+        if !self.visible {
+            return;
+        }
+
+        let mut input = std::mem::take(&mut self.input);
+        input.screen_rect = Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(self.uniform.screen_size[0], self.uniform.screen_size[1]),
+        ));
+        input.pixels_per_point = Some(self.pixels_per_point);
+        input.time = Some(self.start.elapsed().as_secs_f64());
+        input.modifiers = self.modifiers;
+
+        let output = self.context.run(input, run_ui);
+        self.textures_delta.append(output.textures_delta);
+        for primitive in self.context.tessellate(output.shapes) {
+            // Paint callbacks are not supported.
+            let egui::epaint::Primitive::Mesh(mesh) = primitive.primitive else {
+                continue;
+            };
+            let start = self.indices.len() as u32;
+            self.draw_calls.push(DrawCall {
+                clip_rect: primitive.clip_rect,
+                texture_id: mesh.texture_id,
+                indices: start..start + mesh.indices.len() as u32,
+                base_vertex: self.vertices.len() as i32,
+            });
+            self.vertices.extend(mesh.vertices);
+            self.indices.extend(mesh.indices);
+        }
+    }
+
+    /// Apply texture changes and draw what the last `run` laid out.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let delta = std::mem::take(&mut self.textures_delta);
+        for (id, image_delta) in delta.set {
+            self.set_texture(device, queue, id, &image_delta);
+        }
+
+        if !self.draw_calls.is_empty() {
+            if self.vertices.len() > self.capacity[0] {
+                self.capacity[0] = self.vertices.len().next_power_of_two();
+                self.vertex_buffer = create_vertex_buffer(device, self.capacity[0]);
+            }
+            if self.indices.len() > self.capacity[1] {
+                self.capacity[1] = self.indices.len().next_power_of_two();
+                self.index_buffer = create_index_buffer(device, self.capacity[1]);
+            }
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+            queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&self.indices));
+            self.draw(view, encoder);
+        }
+
+        for id in delta.free {
+            self.textures.remove(&id);
+        }
+    }
+
+    fn draw(&self, view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("gui.render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+        for draw_call in &self.draw_calls {
+            let Some(texture) = self.textures.get(&draw_call.texture_id) else {
+                continue;
+            };
+            // The clip rectangle in pixels, within the surface.
+            let clip = |value: f32, max: u32| {
+                ((value * self.pixels_per_point).round().max(0.0) as u32).min(max)
+            };
+            let min = draw_call.clip_rect.min;
+            let max = draw_call.clip_rect.max;
+            let [x, y] = [clip(min.x, self.size[0]), clip(min.y, self.size[1])];
+            let [width, height] = [
+                clip(max.x, self.size[0]).saturating_sub(x),
+                clip(max.y, self.size[1]).saturating_sub(y),
+            ];
+            if width == 0 || height == 0 {
+                continue;
+            }
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.set_bind_group(1, &texture.bind_group, &[]);
+            render_pass.draw_indexed(draw_call.indices.clone(), draw_call.base_vertex, 0..1);
+        }
+    }
+
+    /// Create or patch the texture `id` as egui asked.
+    fn set_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) {
+        let pixels = match &delta.image {
+            egui::ImageData::Color(image) => image.pixels.clone(),
+            egui::ImageData::Font(image) => image.srgba_pixels(1.0).collect(),
+        };
+        let [width, height] = delta.image.size();
+        let size = wgpu::Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        };
+
+        let origin = match delta.pos {
+            Some([x, y]) => wgpu::Origin3d {
+                x: x as u32,
+                y: y as u32,
+                z: 0,
+            },
+            None => {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("gui.texture"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                });
+                let filter = match delta.filter {
+                    egui::TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+                    egui::TextureFilter::Linear => wgpu::FilterMode::Linear,
+                };
+                let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                    mag_filter: filter,
+                    min_filter: filter,
+                    ..Default::default()
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.texture_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(
+                                &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                    label: Some("gui.texture_bind_group"),
+                });
+                self.textures.insert(
+                    id,
+                    GuiTexture {
+                        texture,
+                        bind_group,
+                    },
+                );
+                wgpu::Origin3d::ZERO
+            }
+        };
+        let Some(texture) = self.textures.get(&id) else {
+            log::warn!("gui: patch for missing texture {:?}", id);
+            return;
+        };
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture.texture,
+                mip_level: 0,
+                origin,
+            },
+            bytemuck::cast_slice(&pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * size.width),
+                rows_per_image: std::num::NonZeroU32::new(size.height),
+            },
+            size,
+        );
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    create_buffer(
+        device,
+        "gui.vertex_buffer",
+        capacity * std::mem::size_of::<egui::epaint::Vertex>(),
+        wgpu::BufferUsages::VERTEX,
+    )
+}
+
+fn create_index_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    create_buffer(
+        device,
+        "gui.index_buffer",
+        capacity * std::mem::size_of::<u32>(),
+        wgpu::BufferUsages::INDEX,
+    )
+}
+
+fn create_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    size: usize,
+    usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: size as wgpu::BufferAddress,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// The keys egui uses for editing and navigating.
+fn translate_key(key: VirtualKeyCode) -> Option<egui::Key> {
+    use egui::Key;
+    Some(match key {
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Key::Enter,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Insert => Key::Insert,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        _ => return None,
+    })
+}
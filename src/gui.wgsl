@@ -0,0 +1,47 @@
+// egui meshes, in points from the top left corner with premultiplied sRGB
+// vertex colors.
+
+// Matches `gui::GuiUniform`.
+struct GuiUniform {
+    screen_size: vec2<f32>,
+}
+@group(0) @binding(0)
+var<uniform> gui: GuiUniform;
+@group(1) @binding(0)
+var t_gui: texture_2d<f32>;
+@group(1) @binding(1)
+var s_gui: sampler;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+fn linear_from_srgb(srgb: vec3<f32>) -> vec3<f32> {
+    let lower = srgb / 12.92;
+    let higher = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(higher, lower, srgb < vec3<f32>(0.04045));
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    let ndc = model.position / gui.screen_size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.tex_coords = model.tex_coords;
+    // The surface is sRGB, so blending happens in linear space.
+    out.color = vec4<f32>(linear_from_srgb(model.color.rgb), model.color.a);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * textureSample(t_gui, s_gui, in.tex_coords);
+}
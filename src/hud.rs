@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+
+use instant::Duration;
+
+use crate::text::TextRenderer;
+
+/// Frames averaged for the FPS readout.
+const FRAME_SAMPLES: usize = 60;
+const MARGIN: f32 = 8.0;
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const BACKDROP_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+/// Status text in the top left corner, drawn as the last pass of a frame.
+pub struct Hud {
+    pub text: TextRenderer,
+    frame_times: VecDeque<Duration>,
+    pub visible: bool,
+}
+
+impl Hud {
+    pub fn new(text: TextRenderer) -> Self {
+        Self {
+            text,
+            frame_times: VecDeque::with_capacity(FRAME_SAMPLES),
+            visible: true,
+        }
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        if self.frame_times.len() == FRAME_SAMPLES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(dt);
+    }
+
+    /// Mean frame time over the last `FRAME_SAMPLES` frames.
+    pub fn frame_time(&self) -> Duration {
+        if self.frame_times.is_empty() {
+            return Duration::ZERO;
+        }
+        self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32
+    }
+
+    /// Queue the performance numbers followed by `lines` and draw them.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        lines: &[String],
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let frame_time = self.frame_time().as_secs_f32();
+        let fps = if frame_time > 0.0 {
+            1.0 / frame_time
+        } else {
+            0.0
+        };
+        let mut text = format!("{:.1} fps  {:.2} ms", fps, frame_time * 1000.0);
+        for line in lines {
+            text.push('\n');
+            text.push_str(line);
+        }
+
+        let [width, height] = self.text.measure(&text);
+        self.text.rect(
+            [0.0, 0.0],
+            [width + 2.0 * MARGIN, height + 2.0 * MARGIN],
+            BACKDROP_COLOR,
+        );
+        self.text.text(&text, [MARGIN, MARGIN], TEXT_COLOR);
+        self.text.render(device, queue, view, encoder);
+    }
+}
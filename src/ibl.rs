@@ -0,0 +1,239 @@
+use wgpu::util::DeviceExt;
+
+use crate::{light, reflect::Reflection, render, shader::Shader, texture::Texture};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct IblUniform {
+    face: u32,
+    roughness: f32,
+    _padding: [f32; 2],
+}
+
+/// Image-based lighting maps precomputed from an environment cubemap.
+///
+/// `bind_group` holds the light uniform followed by the maps, which it keeps
+/// alive, and is bound in place of `LightBundle::bind_group` by the material
+/// pipeline.
+pub struct Ibl {
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Ibl {
+    pub const IRRADIANCE_SIZE: u32 = 32;
+    pub const PREFILTERED_SIZE: u32 = 128;
+    /// Roughness 0.0 to 1.0 spread over the mip levels, passed to
+    /// `shader_mtl.wgsl` as `MAX_LOD`.
+    pub const PREFILTERED_MIPS: u32 = 5;
+    pub const BRDF_LUT_SIZE: u32 = 256;
+    const CUBE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+    const LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        environment: &Texture,
+        layout: &wgpu::BindGroupLayout,
+        light: &light::LightBundle,
+    ) -> anyhow::Result<Self> {
+        let source = include_str!("ibl.wgsl");
+        let irradiance_shader = Shader::new(Some("ibl.irradiance"), source).define("IRRADIANCE");
+        let prefilter_shader = Shader::new(Some("ibl.prefilter"), source).define("PREFILTER");
+        let brdf_shader = Shader::new(Some("ibl.brdf"), source).define("BRDF");
+        let reflection = Reflection::new(&[&irradiance_shader, &prefilter_shader, &brdf_shader])?;
+        reflection.check_sizes(&[("params", std::mem::size_of::<IblUniform>())])?;
+
+        let pipeline = |shader: &Shader, format| -> anyhow::Result<wgpu::RenderPipeline> {
+            let layout = reflection.create_pipeline_layout(device, shader, shader.label())?;
+            Ok(render::create_render_pipeline(
+                device,
+                &layout,
+                format,
+                None,
+                &[],
+                shader,
+                &render::PipelineOptions::default(),
+                Some("ibl.render_pipeline"),
+            ))
+        };
+        let irradiance_pipeline = pipeline(&irradiance_shader, Self::CUBE_FORMAT)?;
+        let prefilter_pipeline = pipeline(&prefilter_shader, Self::CUBE_FORMAT)?;
+        let brdf_pipeline = pipeline(&brdf_shader, Self::LUT_FORMAT)?;
+        let environment_layout = reflection.create_bind_group_layout(
+            device,
+            &["t_environment", "s_environment", "params"],
+            Some("ibl.environment_layout"),
+        )?;
+
+        let irradiance = create_target(device, Self::IRRADIANCE_SIZE, 1, 6, "ibl.irradiance");
+        let prefiltered = create_target(
+            device,
+            Self::PREFILTERED_SIZE,
+            Self::PREFILTERED_MIPS,
+            6,
+            "ibl.prefiltered",
+        );
+        let brdf_lut = create_target(device, Self::BRDF_LUT_SIZE, 1, 1, "ibl.brdf_lut");
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("ibl.encoder"),
+        });
+
+        // Each face and mip level is its own render pass with its own
+        // parameters.
+        let mut draws = Vec::new();
+        for face in 0..6 {
+            draws.push((&irradiance_pipeline, &irradiance, 0, face, 0.0));
+            for mip in 0..Self::PREFILTERED_MIPS {
+                let roughness = mip as f32 / (Self::PREFILTERED_MIPS - 1) as f32;
+                draws.push((&prefilter_pipeline, &prefiltered, mip, face, roughness));
+            }
+        }
+        for (pipeline, target, mip, face, roughness) in draws {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("ibl.params"),
+                contents: bytemuck::cast_slice(&[IblUniform {
+                    face,
+                    roughness,
+                    _padding: [0.0; 2],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &environment_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&environment.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&environment.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("ibl.environment_bind_group"),
+            });
+            let view = target_view(&target.texture, mip, face);
+            let mut render_pass = begin(&mut encoder, &view);
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        {
+            let view = target_view(&brdf_lut.texture, 0, 0);
+            let mut render_pass = begin(&mut encoder, &view);
+            render_pass.set_pipeline(&brdf_pipeline);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&irradiance.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&prefiltered.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&brdf_lut.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&prefiltered.sampler),
+                },
+            ],
+            label: Some("ibl.bind_group"),
+        });
+
+        Ok(Self { bind_group })
+    }
+}
+
+/// A square render target, a cubemap when `layers` is 6.
+fn create_target(device: &wgpu::Device, size: u32, mips: u32, layers: u32, label: &str) -> Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: mips,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: if layers == 6 {
+            Ibl::CUBE_FORMAT
+        } else {
+            Ibl::LUT_FORMAT
+        },
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(if layers == 6 {
+            wgpu::TextureViewDimension::Cube
+        } else {
+            wgpu::TextureViewDimension::D2
+        }),
+        ..Default::default()
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    Texture {
+        texture,
+        view,
+        sampler,
+    }
+}
+
+fn target_view(texture: &wgpu::Texture, mip: u32, layer: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("ibl.target_view"),
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: mip,
+        mip_level_count: std::num::NonZeroU32::new(1),
+        base_array_layer: layer,
+        array_layer_count: std::num::NonZeroU32::new(1),
+        ..Default::default()
+    })
+}
+
+fn begin<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("ibl.render_pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    })
+}
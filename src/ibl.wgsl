@@ -0,0 +1,165 @@
+// Precomputes image-based lighting from an environment cubemap. Exactly one
+// of IRRADIANCE, PREFILTER or BRDF selects what `fs_main` renders.
+
+let PI: f32 = 3.14159265359;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.ndc = uv * 2.0 - 1.0;
+    out.clip_position = vec4<f32>(out.ndc, 0.0, 1.0);
+    return out;
+}
+
+fn radical_inverse(index: u32) -> f32 {
+    var bits = index;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(index: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index) / f32(count), radical_inverse(index));
+}
+
+// A GGX-distributed half vector around `n`.
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    var up = vec3<f32>(0.0, 0.0, 1.0);
+    if (abs(n.z) > 0.999) {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+
+#ifdef BRDF
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// Scale and bias to F0 of the split-sum approximation, indexed by
+// (n_dot_v, roughness).
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = in.ndc * 0.5 + 0.5;
+    let n_dot_v = max(uv.x, 0.001);
+    let roughness = 1.0 - uv.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+
+    let sample_count = 128u;
+    var a = 0.0;
+    var b = 0.0;
+    for (var i = 0u; i < sample_count; i = i + 1u) {
+        let h = importance_sample_ggx(hammersley(i, sample_count), n, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        let n_dot_h = max(h.z, 0.0);
+        let v_dot_h = max(dot(v, h), 0.0);
+        if (n_dot_l > 0.0) {
+            let g = geometry_schlick_ggx(n_dot_v, roughness)
+                * geometry_schlick_ggx(n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            a = a + (1.0 - fc) * g_vis;
+            b = b + fc * g_vis;
+        }
+    }
+    return vec4<f32>(a / f32(sample_count), b / f32(sample_count), 0.0, 1.0);
+}
+#else
+struct IblUniform {
+    face: u32,
+    roughness: f32,
+    _padding: vec2<f32>,
+};
+@group(0) @binding(0)
+var t_environment: texture_cube<f32>;
+@group(0) @binding(1)
+var s_environment: sampler;
+@group(0) @binding(2)
+var<uniform> params: IblUniform;
+
+// Matches `texture::cube_face_direction`.
+fn face_direction(face: u32, ndc: vec2<f32>) -> vec3<f32> {
+    let s = ndc.x;
+    let t = -ndc.y;
+    var dir: vec3<f32>;
+    switch (i32(face)) {
+        case 0: { dir = vec3<f32>(1.0, -t, -s); }
+        case 1: { dir = vec3<f32>(-1.0, -t, s); }
+        case 2: { dir = vec3<f32>(s, 1.0, t); }
+        case 3: { dir = vec3<f32>(s, -1.0, -t); }
+        case 4: { dir = vec3<f32>(s, -t, 1.0); }
+        default: { dir = vec3<f32>(-s, -t, -1.0); }
+    }
+    return normalize(dir);
+}
+#endif
+
+#ifdef IRRADIANCE
+// Cosine-weighted average of the environment over the hemisphere around `n`.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = face_direction(params.face, in.ndc);
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(n.y) > 0.999) {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(up, n));
+    up = cross(n, right);
+
+    let delta = 0.1;
+    var irradiance = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi = phi + delta) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta = theta + delta) {
+            let tangent = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let dir = tangent.x * right + tangent.y * up + tangent.z * n;
+            irradiance = irradiance
+                + textureSampleLevel(t_environment, s_environment, dir, 0.0).rgb
+                * cos(theta) * sin(theta);
+            count = count + 1.0;
+        }
+    }
+    return vec4<f32>(PI * irradiance / count, 1.0);
+}
+#endif
+
+#ifdef PREFILTER
+// GGX-convolved environment for one roughness, assuming n = v = r.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = face_direction(params.face, in.ndc);
+    let sample_count = 64u;
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < sample_count; i = i + 1u) {
+        let h = importance_sample_ggx(hammersley(i, sample_count), n, params.roughness);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = max(dot(n, l), 0.0);
+        if (n_dot_l > 0.0) {
+            color = color + textureSampleLevel(t_environment, s_environment, l, 0.0).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+    return vec4<f32>(color / weight, 1.0);
+}
+#endif
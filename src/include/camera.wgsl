@@ -0,0 +1,7 @@
+// Matches `camera::CameraUniform`.
+struct CameraUniform {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+};
+@group(CAMERA_GROUP) @binding(0)
+var<uniform> camera: CameraUniform;
@@ -0,0 +1,21 @@
+// Matches `particles::EmitterUniform`.
+struct Emitter {
+    position: vec3<f32>,
+    spawn_start: u32,
+    direction: vec3<f32>,
+    cos_cone: f32,
+    gravity: vec3<f32>,
+    speed: f32,
+    camera_right: vec3<f32>,
+    lifetime: f32,
+    camera_up: vec3<f32>,
+    size: f32,
+    dt: f32,
+    spawn_count: u32,
+    seed: u32,
+    capacity: u32,
+    // Color over life, sampled from the emitter's gradient.
+    colors: array<vec4<f32>, 16>,
+}
+@group(EMITTER_GROUP) @binding(0)
+var<uniform> emitter: Emitter;
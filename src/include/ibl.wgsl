@@ -0,0 +1,9 @@
+// Filled by `ibl::Ibl`, sharing a group with `light` at binding 0.
+@group(IBL_GROUP) @binding(1)
+var t_irradiance: texture_cube<f32>;
+@group(IBL_GROUP) @binding(2)
+var t_prefiltered: texture_cube<f32>;
+@group(IBL_GROUP) @binding(3)
+var t_brdf: texture_2d<f32>;
+@group(IBL_GROUP) @binding(4)
+var s_ibl: sampler;
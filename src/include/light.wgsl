@@ -0,0 +1,8 @@
+// Matches `light::LightUniform`, a vec3 is 16-byte aligned so the
+// `_padding` fields on the Rust side are implied here.
+struct Light {
+    position: vec3<f32>,
+    color: vec3<f32>,
+}
+@group(LIGHT_GROUP) @binding(0)
+var<uniform> light: Light;
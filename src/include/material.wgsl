@@ -0,0 +1,17 @@
+// The bind group of `model::Material`, always at group 0.
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var s_normal: sampler;
+
+// Matches `model::MaterialUniform`.
+struct Material {
+    opacity: f32,
+    alpha_cutoff: f32,
+}
+@group(0) @binding(4)
+var<uniform> material: Material;
@@ -0,0 +1,37 @@
+// Matches `model::MorphDelta`.
+struct MorphDelta {
+    position: vec4<f32>,
+    normal: vec4<f32>,
+    tangent: vec4<f32>,
+}
+// Matches `model::MorphTargetsUniform`.
+struct MorphTargets {
+    num_vertices: u32,
+    num_targets: u32,
+}
+// Target-major, `num_vertices` per target.
+@group(MORPH_GROUP) @binding(0)
+var<storage, read> morph_deltas: array<MorphDelta>;
+// Instance-major, `num_targets` per instance.
+@group(MORPH_GROUP) @binding(1)
+var<storage, read> morph_weights: array<f32>;
+@group(MORPH_GROUP) @binding(2)
+var<uniform> morph: MorphTargets;
+
+// Add the weighted deltas of every target to `vertex`, in model space.
+fn apply_morph_targets(vertex: VertexInput, vertex_index: u32, instance_index: u32) -> VertexInput {
+    var out = vertex;
+    for (var i = 0u; i < morph.num_targets; i += 1u) {
+        let weight = morph_weights[instance_index * morph.num_targets + i];
+        if (weight == 0.0) {
+            continue;
+        }
+        let delta = morph_deltas[i * morph.num_vertices + vertex_index];
+        out.position += delta.position.xyz * weight;
+        out.normal += delta.normal.xyz * weight;
+#ifdef TANGENTS
+        out.tangent += vec4<f32>(delta.tangent.xyz * weight, 0.0);
+#endif
+    }
+    return out;
+}
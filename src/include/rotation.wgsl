@@ -0,0 +1,6 @@
+// Matches `vertex::RotationUniform`.
+struct RotationUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(ROTATION_GROUP) @binding(0)
+var<uniform> rotation: RotationUniform;
@@ -0,0 +1,10 @@
+// Matches `model::ModelVertex::desc()`.
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+#ifdef TANGENTS
+    // Along +u, with the bitangent's handedness in w.
+    @location(3) tangent: vec4<f32>,
+#endif
+}
@@ -1,14 +1,39 @@
+mod animation;
+mod assets;
 mod buffer;
 mod camera;
 mod data;
+mod debug_draw;
+mod debug_view;
 mod depth;
+mod embedded;
+mod gui;
+mod hud;
+mod ibl;
 mod light;
+mod loader;
+mod lod;
 mod model;
+mod morph;
+mod optimize;
+mod particles;
+mod primitives;
+mod profiler;
+mod reflect;
 mod render;
+#[cfg(not(target_arch = "wasm32"))]
+mod resolver;
 mod resources;
+mod shader;
+mod skinned;
+mod skybox;
 mod state;
+mod tangents;
+mod terrain;
+mod text;
 mod texture;
 mod vertex;
+mod vfs;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -63,6 +88,11 @@ pub async fn run() {
     }
 
     let mut state = State::new(&window).await.unwrap();
+    state.assets.set_progress_callback(|progress| {
+        if progress.is_done() {
+            log::info!("loaded {}", progress);
+        }
+    });
     let mut last_render_time = instant::Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
@@ -72,34 +102,32 @@ pub async fn run() {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == window.id() => {
+            } if window_id == window.id() && !input(&mut state, event) => {
                 //println!("..000000000000000000000000000000000");
-                if !input(&mut state, event) {
-                    match event {
-                        #[cfg(not(target_arch = "wasm32"))]
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Resized(physical_size) => {
-                            /*println!(
-                                "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!00000>{:?}",
-                                physical_size
-                            );*/
-                            state.resize(*physical_size);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &&mut so we have to dereference it twice
-                            state.resize(**new_inner_size);
-                        }
-                        _ => {}
+                match event {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(physical_size) => {
+                        /*println!(
+                            "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!00000>{:?}",
+                            physical_size
+                        );*/
+                        state.resize(*physical_size);
+                    }
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        // new_inner_size is &&mut so we have to dereference it twice
+                        state.resize(**new_inner_size);
                     }
+                    _ => {}
                 }
             }
 
@@ -128,13 +156,11 @@ pub async fn run() {
             Event::DeviceEvent {
                 event: DeviceEvent::MouseMotion { delta },
                 ..
-            } => {
-                if state.camera_bundle.mouse_pressed {
-                    state
-                        .camera_bundle
-                        .controller
-                        .process_mouse(delta.0, delta.1)
-                }
+            } if state.camera_bundle.mouse_pressed => {
+                state
+                    .camera_bundle
+                    .controller
+                    .process_mouse(delta.0, delta.1)
             }
 
             _ => {}
@@ -143,6 +169,11 @@ pub async fn run() {
 }
 
 fn input(state: &mut State, event: &WindowEvent) -> bool {
+    // The panel gets first pick, keeping only what lands on it.
+    if state.gui.handle_event(event) {
+        return true;
+    }
+
     state.clear_color = wgpu::Color {
         r: 0.0,
         g: 0.0,
@@ -211,6 +242,41 @@ fn input(state: &mut State, event: &WindowEvent) -> bool {
                 state.keys.background = !state.keys.background;
                 log::info!("B changed background to {}", state.keys.background);
             }
+            VirtualKeyCode::K => {
+                state.keys.skybox = !state.keys.skybox;
+                log::info!("K changed skybox to {}", state.keys.skybox);
+            }
+            VirtualKeyCode::V => {
+                state.keys.debug_view = state.keys.debug_view.next();
+                log::info!("V changed debug_view to {:?}", state.keys.debug_view);
+            }
+            VirtualKeyCode::O => {
+                state.keys.alpha_mode = model::AlphaMode::next(state.keys.alpha_mode);
+                log::info!("O changed alpha_mode to {:?}", state.keys.alpha_mode);
+            }
+            VirtualKeyCode::G => {
+                state.keys.gizmos = !state.keys.gizmos;
+                log::info!("G changed gizmos to {}", state.keys.gizmos);
+            }
+            VirtualKeyCode::H => {
+                state.hud.visible = !state.hud.visible;
+                log::info!("H changed hud to {}", state.hud.visible);
+            }
+            VirtualKeyCode::U => {
+                state.gui.visible = !state.gui.visible;
+                log::info!("U changed gui to {}", state.gui.visible);
+            }
+            VirtualKeyCode::T => {
+                let path = "trace.json";
+                match std::fs::write(path, state.profiler.chrome_trace()) {
+                    Ok(()) => log::info!("T wrote a Chrome trace to {}", path),
+                    Err(e) => log::error!("T couldn't write {}: {}", path, e),
+                }
+            }
+            VirtualKeyCode::X => {
+                state.debug_draw.depth_test = !state.debug_draw.depth_test;
+                log::info!("X changed depth_test to {}", state.debug_draw.depth_test);
+            }
             _ => {
                 return state
                     .camera_bundle
@@ -13,12 +13,16 @@ pub struct LightUniform {
 pub struct LightBundle {
     pub uniform: LightUniform,
     pub buffer: wgpu::Buffer,
-    pub layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 }
 
 impl LightBundle {
-    pub fn new(device: &wgpu::Device, position: [f32; 3], color: [f32; 3]) -> Self {
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        position: [f32; 3],
+        color: [f32; 3],
+    ) -> Self {
         let uniform = LightUniform {
             position,
             _padding: 0,
@@ -30,21 +34,8 @@ impl LightBundle {
             contents: bytemuck::cast_slice(&[uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
-        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: None,
-        });
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &layout,
+            layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
                 resource: buffer.as_entire_binding(),
@@ -55,7 +46,6 @@ impl LightBundle {
         LightBundle {
             uniform,
             buffer,
-            layout,
             bind_group,
         }
     }
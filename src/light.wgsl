@@ -1,19 +1,8 @@
 // light.wgsl
 // Vertex shader
 
-struct Camera {
-    view_pos: vec4<f32>,
-    view_proj: mat4x4<f32>,
-}
-@group(0) @binding(0)
-var<uniform> camera: Camera;
-
-struct Light {
-    position: vec3<f32>,
-    color: vec3<f32>,
-}
-@group(1) @binding(0)
-var<uniform> light: Light;
+#include "camera.wgsl"
+#include "light.wgsl"
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
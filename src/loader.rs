@@ -0,0 +1,160 @@
+use std::sync::mpsc;
+
+use crate::{
+    assets::Handle,
+    model,
+    resources::{self, ObjData},
+    texture,
+};
+
+/// Something to load, everything but the upload to the GPU, which is left
+/// to the main thread.
+pub enum Job {
+    Texture {
+        handle: Handle<texture::Texture>,
+        path: String,
+        is_normal_map: bool,
+    },
+    Model {
+        handle: Handle<model::Model>,
+        path: String,
+    },
+}
+
+/// A finished `Job`, ready to upload.
+pub enum Loaded {
+    Texture {
+        handle: Handle<texture::Texture>,
+        is_normal_map: bool,
+        image: anyhow::Result<image::DynamicImage>,
+    },
+    Model {
+        handle: Handle<model::Model>,
+        data: anyhow::Result<ObjData>,
+    },
+}
+
+impl Job {
+    async fn run(&self) -> Loaded {
+        match self {
+            Job::Texture {
+                handle,
+                path,
+                is_normal_map,
+            } => Loaded::Texture {
+                handle: handle.clone(),
+                is_normal_map: *is_normal_map,
+                image: resources::load_image(path).await,
+            },
+            Job::Model { handle, path } => Loaded::Model {
+                handle: handle.clone(),
+                data: resources::load_obj(path).await,
+            },
+        }
+    }
+
+    /// The job failing with `error`, for when `run` panics.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn fail(&self, error: anyhow::Error) -> Loaded {
+        match self {
+            Job::Texture {
+                handle,
+                is_normal_map,
+                ..
+            } => Loaded::Texture {
+                handle: handle.clone(),
+                is_normal_map: *is_normal_map,
+                image: Err(error),
+            },
+            Job::Model { handle, .. } => Loaded::Model {
+                handle: handle.clone(),
+                data: Err(error),
+            },
+        }
+    }
+}
+
+/// Runs jobs without blocking the event loop, on worker threads natively and
+/// as spawned futures on the web, where everything shares the one thread.
+pub struct Loader {
+    #[cfg(not(target_arch = "wasm32"))]
+    jobs: mpsc::Sender<Job>,
+    #[cfg(target_arch = "wasm32")]
+    sender: mpsc::Sender<Loaded>,
+    results: mpsc::Receiver<Loaded>,
+}
+
+impl Loader {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new() -> anyhow::Result<Self> {
+        use std::{
+            panic::AssertUnwindSafe,
+            sync::{Arc, Mutex},
+        };
+
+        let workers = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .clamp(1, 4);
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let (sender, results) = mpsc::channel();
+        for i in 0..workers {
+            let receiver = receiver.clone();
+            let sender = sender.clone();
+            std::thread::Builder::new()
+                .name(format!("loader {}", i))
+                .spawn(move || loop {
+                    // Only one worker waits on the queue at a time, with the
+                    // rest waiting for the lock.
+                    let job = match receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        // The `Loader` was dropped.
+                        Err(_) => break,
+                    };
+                    // A panic only fails the one asset, rather than leaving it
+                    // loading forever and the worker gone.
+                    let loaded = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                        pollster::block_on(job.run())
+                    }))
+                    .unwrap_or_else(|panic| {
+                        let message = panic
+                            .downcast_ref::<&str>()
+                            .copied()
+                            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                            .unwrap_or("unknown panic");
+                        job.fail(anyhow::anyhow!("panicked: {}", message))
+                    });
+                    if sender.send(loaded).is_err() {
+                        break;
+                    }
+                })?;
+        }
+        Ok(Self { jobs, results })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn new() -> anyhow::Result<Self> {
+        let (sender, results) = mpsc::channel();
+        Ok(Self { sender, results })
+    }
+
+    /// Start on `job`, to be picked up from `finished` when it's done.
+    pub fn spawn(&self, job: Job) {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                let sender = self.sender.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let _ = sender.send(job.run().await);
+                });
+            } else {
+                // The workers only stop once this is dropped.
+                self.jobs.send(job).unwrap();
+            }
+        }
+    }
+
+    /// The jobs finished since last time, without waiting for any.
+    pub fn finished(&self) -> Vec<Loaded> {
+        self.results.try_iter().collect()
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use bytemuck::Zeroable;
+use cgmath::{prelude::*, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::{Camera, Projection},
+    model::{self, MeshData, ModelVertex},
+    optimize,
+    vertex::{Instance, InstanceRaw},
+};
+
+/// A quadric error metric, the summed squared distance to a set of planes,
+/// from Garland and Heckbert's "Surface Simplification Using Quadric Error
+/// Metrics".
+#[derive(Copy, Clone, Default)]
+struct Quadric {
+    /// The symmetric matrix xx, xy, xz, yy, yz, zz.
+    a: [f64; 6],
+    b: [f64; 3],
+    c: f64,
+}
+
+impl Quadric {
+    /// The plane through `a`, `b` and `c`, weighted by the triangle's area.
+    fn triangle(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Self {
+        let cross = (b - a).cross(c - a).cast::<f64>().unwrap();
+        let area = cross.magnitude() / 2.0;
+        if area == 0.0 {
+            return Self::default();
+        }
+        let n = cross / (2.0 * area);
+        let d = -n.dot(a.cast::<f64>().unwrap());
+        Self {
+            a: [
+                n.x * n.x,
+                n.x * n.y,
+                n.x * n.z,
+                n.y * n.y,
+                n.y * n.z,
+                n.z * n.z,
+            ]
+            .map(|x| x * area),
+            b: [n.x * d, n.y * d, n.z * d].map(|x| x * area),
+            c: d * d * area,
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        for i in 0..6 {
+            self.a[i] += other.a[i];
+        }
+        for i in 0..3 {
+            self.b[i] += other.b[i];
+        }
+        self.c += other.c;
+    }
+
+    fn error(&self, p: Vector3<f32>) -> f64 {
+        let [x, y, z] = [p.x as f64, p.y as f64, p.z as f64];
+        let [xx, xy, xz, yy, yz, zz] = self.a;
+        x * x * xx
+            + 2.0 * x * y * xy
+            + 2.0 * x * z * xz
+            + y * y * yy
+            + 2.0 * y * z * yz
+            + z * z * zz
+            + 2.0 * (x * self.b[0] + y * self.b[1] + z * self.b[2])
+            + self.c
+    }
+}
+
+/// Collapse edges of `indices` with the least quadric error until there are
+/// `target` indices or no edge can go. No vertices are moved or added, so
+/// the result can share the vertex buffer. Vertices on borders and on seams,
+/// where vertices with different normals or texture coordinates share a
+/// position, are kept in place so that no holes or smears open up. Edges
+/// whose collapse would turn a triangle around or fold two onto each other
+/// are left.
+pub fn simplify(vertices: &[ModelVertex], indices: &[u32], target: usize) -> Vec<u32> {
+    let position = |index: u32| Vector3::from(vertices[index as usize].position);
+
+    // Vertices at the same position, and how many triangles share each edge
+    // between them.
+    let mut positions = HashMap::new();
+    let group = vertices
+        .iter()
+        .map(|vertex| {
+            let len = positions.len();
+            *positions
+                .entry(vertex.position.map(f32::to_bits))
+                .or_insert(len)
+        })
+        .collect::<Vec<_>>();
+    let mut locked = vec![false; vertices.len()];
+    let mut group_size = vec![0; positions.len()];
+    for &g in &group {
+        group_size[g] += 1;
+    }
+    let mut edges = HashMap::new();
+    for corners in indices.chunks_exact(3) {
+        for i in 0..3 {
+            let (a, b) = (
+                group[corners[i] as usize],
+                group[corners[(i + 1) % 3] as usize],
+            );
+            *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+    let mut border = vec![false; positions.len()];
+    for (&(a, b), &count) in &edges {
+        if count == 1 {
+            border[a] = true;
+            border[b] = true;
+        }
+    }
+    for (v, &g) in group.iter().enumerate() {
+        locked[v] = border[g] || group_size[g] > 1;
+    }
+
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    for corners in indices.chunks_exact(3) {
+        let quadric = Quadric::triangle(
+            position(corners[0]),
+            position(corners[1]),
+            position(corners[2]),
+        );
+        for &index in corners {
+            quadrics[index as usize].add(&quadric);
+        }
+    }
+
+    let mut indices = indices.to_vec();
+    // Collapse a share of the edges at a time, each with a neighbourhood of
+    // its own so that the checks below don't go stale.
+    while indices.len() > target {
+        let mut triangles_of = vec![Vec::new(); vertices.len()];
+        let mut triangles_of_group = vec![Vec::new(); positions.len()];
+        for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+            for &index in corners {
+                triangles_of[index as usize].push(triangle);
+                triangles_of_group[group[index as usize]].push(triangle);
+            }
+        }
+        let mut candidates = HashMap::new();
+        for corners in indices.chunks_exact(3) {
+            for i in 0..3 {
+                let (from, to) = (corners[i], corners[(i + 1) % 3]);
+                for (from, to) in [(from, to), (to, from)] {
+                    if locked[from as usize] {
+                        continue;
+                    }
+                    let mut quadric = quadrics[from as usize];
+                    quadric.add(&quadrics[to as usize]);
+                    let error = quadric.error(position(to));
+                    let best = candidates.entry(from).or_insert((error, to));
+                    if error < best.0 {
+                        *best = (error, to);
+                    }
+                }
+            }
+        }
+        let mut candidates = candidates
+            .into_iter()
+            .map(|(from, (error, to))| (error, from, to))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut remap = (0..vertices.len() as u32).collect::<Vec<_>>();
+        let mut touched = vec![false; vertices.len()];
+        let mut remaining = indices.len();
+        for (_, from, to) in candidates {
+            if remaining <= target {
+                break;
+            }
+            if touched[from as usize] || touched[to as usize] {
+                continue;
+            }
+            let around = &triangles_of[from as usize];
+            let flips = around.iter().any(|&triangle| {
+                let corners = &indices[triangle * 3..triangle * 3 + 3];
+                if corners.contains(&to) {
+                    return false;
+                }
+                let [a, b, c] = [corners[0], corners[1], corners[2]].map(position);
+                let moved = [corners[0], corners[1], corners[2]].map(|index| {
+                    if index == from {
+                        to
+                    } else {
+                        index
+                    }
+                });
+                let [a2, b2, c2] = moved.map(position);
+                let before = (b - a).cross(c - a);
+                let after = (b2 - a2).cross(c2 - a2);
+                // Small turns add up over many collapses, so the vertex
+                // normals, which stay put, are checked too.
+                let normal = moved
+                    .iter()
+                    .map(|&index| Vector3::from(vertices[index as usize].normal))
+                    .sum::<Vector3<f32>>();
+                after.dot(before) <= 0.0 || after.dot(normal) <= 0.0
+            });
+            // Only the triangles on the edge may share both ends, or the
+            // collapse folds a pair of others onto each other, back to back.
+            // Seams split no edges here, so vertices are taken by position.
+            let (from_group, to_group) = (group[from as usize], group[to as usize]);
+            let neighbours = |g: usize| {
+                triangles_of_group[g]
+                    .iter()
+                    .flat_map(|&triangle| &indices[triangle * 3..triangle * 3 + 3])
+                    .map(|&index| group[index as usize])
+                    .filter(|&other| other != g)
+                    .collect::<HashSet<_>>()
+            };
+            let on_edge = triangles_of_group[from_group]
+                .iter()
+                .filter(|&&triangle| {
+                    indices[triangle * 3..triangle * 3 + 3]
+                        .iter()
+                        .any(|&index| group[index as usize] == to_group)
+                })
+                .count();
+            let folds = neighbours(from_group)
+                .intersection(&neighbours(to_group))
+                .count()
+                > on_edge;
+            if flips || folds {
+                continue;
+            }
+
+            remap[from as usize] = to;
+            let quadric = quadrics[from as usize];
+            quadrics[to as usize].add(&quadric);
+            for &triangle in around {
+                let corners = &indices[triangle * 3..triangle * 3 + 3];
+                if corners.contains(&to) {
+                    remaining -= 3;
+                }
+                for &index in corners {
+                    touched[index as usize] = true;
+                }
+            }
+        }
+        if remaining == indices.len() {
+            break;
+        }
+
+        indices = indices
+            .chunks_exact(3)
+            .map(|corners| [corners[0], corners[1], corners[2]].map(|i| remap[i as usize]))
+            .filter(|[a, b, c]| a != b && b != c && c != a)
+            .flatten()
+            .collect();
+    }
+    indices
+}
+
+/// Each level of detail after `data` itself, keeping `ratios` of its
+/// triangles, simplified from the full mesh and ordered for the vertex
+/// cache.
+pub fn generate(data: &MeshData, ratios: &[f32]) -> Vec<Vec<u32>> {
+    ratios
+        .iter()
+        .map(|ratio| {
+            let target = (data.indices.len() as f32 / 3.0 * ratio) as usize * 3;
+            let mut lod = simplify(&data.vertices, &data.indices, target);
+            optimize::optimize_vertex_cache(&mut lod, data.vertices.len());
+            lod
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct LodOptions {
+    /// The share of the triangles kept at each level after the first.
+    pub ratios: Vec<f32>,
+    /// The screen size each level after the first is used below, as the
+    /// radius of the mesh's bounds over half the height of the screen.
+    pub screen_sizes: Vec<f32>,
+    /// How far past a screen size an instance has to go to switch level, as
+    /// a fraction of it, so that instances right at one don't flicker.
+    pub hysteresis: f32,
+}
+
+/// Instances of a mesh with levels of detail, each drawing the coarsest
+/// level that still looks right at its size on the screen.
+pub struct LodInstances {
+    pub mesh: model::Mesh,
+    pub options: LodOptions,
+    /// Of a sphere around the mesh's origin that holds all of it.
+    radius: f32,
+    instances: Vec<Instance>,
+    levels: Vec<usize>,
+    /// The instances drawing each level, which are grouped together in
+    /// `instance_buffer`.
+    ranges: Vec<Range<u32>>,
+    instance_buffer: wgpu::Buffer,
+}
+
+impl LodInstances {
+    pub fn new(
+        device: &wgpu::Device,
+        data: &MeshData,
+        name: &str,
+        material: usize,
+        options: LodOptions,
+        instances: Vec<Instance>,
+    ) -> Self {
+        let (data, _) = optimize::optimize(data, name);
+        let lods = generate(&data, &options.ratios);
+        for (ratio, lod) in options.ratios.iter().zip(&lods) {
+            log::info!(
+                "{}: {} of {} triangles kept for a ratio of {}",
+                name,
+                lod.len() / 3,
+                data.indices.len() / 3,
+                ratio,
+            );
+        }
+        let radius = data
+            .vertices
+            .iter()
+            .map(|vertex| Vector3::from(vertex.position).magnitude())
+            .fold(0.0, f32::max);
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Instance Buffer", name)),
+            contents: bytemuck::cast_slice(&instance_data),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let mut ranges = vec![0..0; lods.len() + 1];
+        ranges[0] = 0..instances.len() as u32;
+
+        Self {
+            mesh: data.create_lod_mesh(device, name, material, &lods),
+            options,
+            radius,
+            levels: vec![0; instances.len()],
+            instances,
+            ranges,
+            instance_buffer,
+        }
+    }
+
+    /// The instances drawing each level of detail, finest first.
+    pub fn ranges(&self) -> &[Range<u32>] {
+        &self.ranges
+    }
+
+    /// Pick each instance's level for the camera, and regroup the instance
+    /// buffer by level.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, projection: &Projection) {
+        let scale = 1.0 / (projection.fovy / 2.0).tan();
+        let screen_sizes = &self.options.screen_sizes;
+        let hysteresis = self.options.hysteresis;
+        let coarsest = screen_sizes.len().min(self.mesh.lods.len() - 1);
+        for (instance, level) in self.instances.iter().zip(&mut self.levels) {
+            let distance = (instance.position - camera.position.to_vec())
+                .magnitude()
+                .max(self.radius);
+            let size = self.radius / distance * scale;
+            *level = (*level).min(coarsest);
+            while *level < coarsest && size < screen_sizes[*level] * (1.0 - hysteresis) {
+                *level += 1;
+            }
+            while *level > 0 && size > screen_sizes[*level - 1] * (1.0 + hysteresis) {
+                *level -= 1;
+            }
+        }
+
+        let mut counts = vec![0; self.ranges.len()];
+        for &level in &self.levels {
+            counts[level] += 1;
+        }
+        let mut start = 0;
+        for (range, count) in self.ranges.iter_mut().zip(counts) {
+            *range = start..start + count;
+            start += count;
+        }
+        let mut instance_data = vec![InstanceRaw::zeroed(); self.instances.len()];
+        let mut next = self
+            .ranges
+            .iter()
+            .map(|range| range.start)
+            .collect::<Vec<_>>();
+        for (instance, &level) in self.instances.iter().zip(&self.levels) {
+            instance_data[next[level] as usize] = instance.to_raw();
+            next[level] += 1;
+        }
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&instance_data),
+        );
+    }
+
+    /// Draw each level of detail for its instances, with whatever pipeline
+    /// and bind groups are set.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, self.mesh.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.mesh.index_buffer.slice(..), self.mesh.index_format);
+        for (lod, instances) in self.mesh.lods.iter().zip(&self.ranges) {
+            if !instances.is_empty() {
+                render_pass.draw_indexed(lod.clone(), 0, instances.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives;
+
+    fn position(data: &MeshData, index: u32) -> [u32; 3] {
+        data.vertices[index as usize].position.map(f32::to_bits)
+    }
+
+    /// Positions of `data` shared by more than one vertex.
+    fn seams(data: &MeshData) -> HashSet<[u32; 3]> {
+        let mut count = HashMap::new();
+        for i in 0..data.vertices.len() as u32 {
+            *count.entry(position(data, i)).or_insert(0) += 1;
+        }
+        count
+            .into_iter()
+            .filter(|&(_, count)| count > 1)
+            .map(|(position, _)| position)
+            .collect()
+    }
+
+    /// Edges used by only one triangle.
+    fn borders(indices: &[u32]) -> HashSet<(u32, u32)> {
+        let mut count = HashMap::new();
+        for corners in indices.chunks_exact(3) {
+            for i in 0..3 {
+                let (a, b) = (corners[i], corners[(i + 1) % 3]);
+                *count.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        count
+            .into_iter()
+            .filter(|&(_, count)| count == 1)
+            .map(|(edge, _)| edge)
+            .collect()
+    }
+
+    /// Panics if any triangle faces away from where `outward` says it should
+    /// at its middle.
+    fn assert_no_flips(
+        vertices: &[ModelVertex],
+        indices: &[u32],
+        outward: impl Fn(Vector3<f32>) -> Vector3<f32>,
+    ) {
+        for corners in indices.chunks_exact(3) {
+            let [a, b, c] = [corners[0], corners[1], corners[2]]
+                .map(|index| Vector3::from(vertices[index as usize].position));
+            let normal = (b - a).cross(c - a);
+            // Slivers may stand on edge, but none may face the other way.
+            let facing = normal
+                .normalize()
+                .dot(outward((a + b + c) / 3.0).normalize());
+            assert!(facing > -1e-3, "{:?} faces {}", corners, facing);
+        }
+    }
+
+    #[test]
+    fn reaches_target() {
+        // Welded into one closed surface, with nothing to lock.
+        let sphere = primitives::icosphere(1.0, 3);
+        let mut welded = HashMap::new();
+        let mut data = MeshData::default();
+        for &index in &sphere.indices {
+            let vertex = sphere.vertices[index as usize];
+            let len = data.vertices.len() as u32;
+            let index = *welded
+                .entry(vertex.position.map(f32::to_bits))
+                .or_insert_with(|| {
+                    data.vertices.push(vertex);
+                    len
+                });
+            data.indices.push(index);
+        }
+        assert!(seams(&data).is_empty());
+
+        let target = data.indices.len() / 4;
+        let lod = simplify(&data.vertices, &data.indices, target);
+        // Each collapse takes two triangles.
+        assert!(
+            lod.len() <= target && lod.len() + 6 >= target,
+            "{}",
+            lod.len()
+        );
+        assert!(borders(&lod).is_empty());
+        assert_no_flips(&data.vertices, &lod, |p| p);
+    }
+
+    #[test]
+    fn borders_locked() {
+        let data = primitives::plane([1.0, 1.0], [8, 8]);
+        let lod = simplify(&data.vertices, &data.indices, 0);
+        assert!(lod.len() < data.indices.len() / 2, "{}", lod.len());
+        assert_eq!(borders(&lod), borders(&data.indices));
+        assert_no_flips(&data.vertices, &lod, |_| Vector3::unit_y());
+    }
+
+    #[test]
+    fn seams_locked() {
+        let data = primitives::icosphere(1.0, 3);
+        let seams = seams(&data);
+        assert!(!seams.is_empty());
+        let lod = simplify(&data.vertices, &data.indices, 0);
+        assert!(lod.len() < data.indices.len() / 2, "{}", lod.len());
+        // A pole vertex of its own may go with its one triangle, but none of
+        // the positions may.
+        let used = lod
+            .iter()
+            .map(|&i| position(&data, i))
+            .collect::<HashSet<_>>();
+        assert!(seams.is_subset(&used));
+        assert_no_flips(&data.vertices, &lod, |p| p);
+    }
+}
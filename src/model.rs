@@ -1,6 +1,9 @@
-use std::ops::Range;
+use std::{ops::Range, sync::Arc};
 
-use crate::texture;
+use anyhow::{anyhow, Result};
+use wgpu::util::DeviceExt;
+
+use crate::{render, shader::Shader, texture};
 
 pub trait Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
@@ -12,8 +15,9 @@ pub struct ModelVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
-    pub tangent: [f32; 3],
-    pub bitangent: [f32; 3],
+    /// Along +u, with `w` the handedness of the bitangent, which shaders
+    /// rebuild as `cross(normal, tangent.xyz) * tangent.w` like MikkTSpace.
+    pub tangent: [f32; 4],
 }
 
 impl Vertex for ModelVertex {
@@ -38,16 +42,10 @@ impl Vertex for ModelVertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 },
-                // Tangent and bitangent
                 wgpu::VertexAttribute {
                     offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
                     shader_location: 3,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
-                    shader_location: 4,
-                    format: wgpu::VertexFormat::Float32x3,
+                    format: wgpu::VertexFormat::Float32x4,
                 },
             ],
         }
@@ -59,10 +57,72 @@ pub struct Model {
     pub materials: Vec<Material>,
 }
 
+/// How a material's alpha is used.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    /// Cut out where alpha is below `MaterialUniform::alpha_cutoff`.
+    Mask,
+    /// Blended over what is behind it, so drawn after everything opaque,
+    /// back to front and without writing depth.
+    Blend,
+}
+
+impl AlphaMode {
+    pub const ALL: [Self; 3] = [Self::Opaque, Self::Mask, Self::Blend];
+
+    /// The override after `alpha_mode`, going from none to each mode in
+    /// turn and back.
+    pub fn next(alpha_mode: Option<Self>) -> Option<Self> {
+        let Some(current) = alpha_mode else {
+            return Some(Self::ALL[0]);
+        };
+        let index = Self::ALL.iter().position(|&mode| mode == current).unwrap();
+        Self::ALL.get(index + 1).copied()
+    }
+
+    /// Select this mode in a shader, which checks `ALPHA_MASK` and
+    /// `ALPHA_BLEND` with `#ifdef`.
+    pub fn define(self, shader: Shader) -> Shader {
+        match self {
+            Self::Opaque => shader,
+            Self::Mask => shader.define("ALPHA_MASK"),
+            Self::Blend => shader.define("ALPHA_BLEND"),
+        }
+    }
+
+    pub fn pipeline_options(self) -> render::PipelineOptions {
+        match self {
+            Self::Opaque | Self::Mask => render::PipelineOptions::default(),
+            Self::Blend => render::PipelineOptions {
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                depth_write_enabled: false,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    /// Multiplies the diffuse texture's alpha, from the MTL `d` value.
+    pub opacity: f32,
+    pub alpha_cutoff: f32,
+}
+
 pub struct Material {
-    pub name: String,
-    pub diffuse_texture: texture::Texture,
-    pub normal_texture: texture::Texture,
+    /// Shared with other materials using the same files. Only `bind_group` is
+    /// drawn with, but holding these keeps the textures from being collected
+    /// as unused.
+    #[allow(dead_code)]
+    pub diffuse_texture: Arc<texture::Texture>,
+    #[allow(dead_code)]
+    pub normal_texture: Arc<texture::Texture>,
+    pub alpha_mode: AlphaMode,
+    pub uniform: MaterialUniform,
+    pub buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
@@ -70,10 +130,23 @@ impl Material {
     pub fn new(
         device: &wgpu::Device,
         name: &str,
-        diffuse_texture: texture::Texture,
-        normal_texture: texture::Texture,
+        diffuse_texture: impl Into<Arc<texture::Texture>>,
+        normal_texture: impl Into<Arc<texture::Texture>>,
+        alpha_mode: AlphaMode,
+        opacity: f32,
         layout: &wgpu::BindGroupLayout,
     ) -> Self {
+        let diffuse_texture: Arc<texture::Texture> = diffuse_texture.into();
+        let normal_texture: Arc<texture::Texture> = normal_texture.into();
+        let uniform = MaterialUniform {
+            opacity,
+            alpha_cutoff: 0.5,
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(name),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout,
             entries: &[
@@ -93,57 +166,207 @@ impl Material {
                     binding: 3,
                     resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffer.as_entire_binding(),
+                },
             ],
             label: Some(name),
         });
 
         Self {
-            name: String::from(name),
             diffuse_texture,
             normal_texture,
+            alpha_mode,
+            uniform,
+            buffer,
             bind_group,
         }
     }
 
-    pub fn desc<'a>() -> wgpu::BindGroupLayoutDescriptor<'a> {
-        wgpu::BindGroupLayoutDescriptor {
+    /// Blended below full opacity, like `load_obj` does for an MTL `d`.
+    pub fn set_opacity(&mut self, queue: &wgpu::Queue, opacity: f32) {
+        self.uniform.opacity = opacity;
+        if opacity < 1.0 {
+            self.alpha_mode = AlphaMode::Blend;
+        } else if self.alpha_mode == AlphaMode::Blend {
+            self.alpha_mode = AlphaMode::Opaque;
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+}
+
+/// How far a morph target moves one vertex, padded for storage buffers.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MorphDelta {
+    pub position: [f32; 4],
+    pub normal: [f32; 4],
+    pub tangent: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MorphTargetsUniform {
+    pub num_vertices: u32,
+    pub num_targets: u32,
+}
+
+/// Blend shapes of a mesh, with a weight per target for each instance.
+///
+/// Bound as the `morph_*` bindings of `include/morph.wgsl`, which needs
+/// storage buffers in the vertex stage.
+pub struct MorphTargets {
+    pub names: Vec<String>,
+    /// Instance-major, `names.len()` per instance.
+    weights: Vec<f32>,
+    dirty: bool,
+    weight_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl MorphTargets {
+    /// `deltas` holds `num_vertices` deltas for each of the `names`, one
+    /// target after the other.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        names: Vec<String>,
+        deltas: &[MorphDelta],
+        num_instances: usize,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform = MorphTargetsUniform {
+            num_vertices: (deltas.len() / names.len().max(1)) as u32,
+            num_targets: names.len() as u32,
+        };
+        let weights = vec![0.0; num_instances * names.len()];
+        let delta_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Morph Deltas", label)),
+            contents: bytemuck::cast_slice(deltas),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let weight_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Morph Weights", label)),
+            contents: bytemuck::cast_slice(&weights),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Morph Targets", label)),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
             entries: &[
-                // View
-                wgpu::BindGroupLayoutEntry {
+                wgpu::BindGroupEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
+                    resource: delta_buffer.as_entire_binding(),
                 },
-                // Sampler
-                wgpu::BindGroupLayoutEntry {
+                wgpu::BindGroupEntry {
                     binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
+                    resource: weight_buffer.as_entire_binding(),
                 },
-                wgpu::BindGroupLayoutEntry {
+                wgpu::BindGroupEntry {
                     binding: 2,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
+                    resource: uniform_buffer.as_entire_binding(),
                 },
             ],
-            label: None,
+            label: Some(label),
+        });
+
+        Self {
+            names,
+            weights,
+            dirty: false,
+            weight_buffer,
+            bind_group,
+        }
+    }
+}
+
+/// Vertices and triangle indices on the CPU, as made by `primitives` and
+/// `terrain` before they're uploaded.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// Append `other`, offsetting its indices past the vertices already here.
+    pub fn append(&mut self, other: &MeshData) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices
+            .extend(other.indices.iter().map(|index| index + offset));
+    }
+
+    /// Upload to a mesh drawn with `material`, with 16 bit indices when
+    /// there are few enough vertices.
+    pub fn create_mesh(&self, device: &wgpu::Device, name: &str, material: usize) -> Mesh {
+        self.create_lod_mesh(device, name, material, &[])
+    }
+
+    /// Like `create_mesh`, along with `Mesh::unindexed_buffer` for the
+    /// meshes `debug_view::DebugViews` draws.
+    pub fn create_debug_mesh(&self, device: &wgpu::Device, name: &str, material: usize) -> Mesh {
+        Mesh {
+            unindexed_buffer: Mesh::create_unindexed_buffer(
+                device,
+                &format!("{:?} Unindexed Buffer", name),
+                &self.vertices,
+                &self.indices,
+            ),
+            ..self.create_mesh(device, name, material)
+        }
+    }
+
+    /// Like `create_mesh`, with coarser levels of detail made from the same
+    /// vertices after the full mesh in the index buffer.
+    pub fn create_lod_mesh(
+        &self,
+        device: &wgpu::Device,
+        name: &str,
+        material: usize,
+        lods: &[Vec<u32>],
+    ) -> Mesh {
+        let mut all_indices = self.indices.clone();
+        let mut ranges = Vec::with_capacity(lods.len() + 1);
+        ranges.push(0..self.indices.len() as u32);
+        for lod in lods {
+            let start = all_indices.len() as u32;
+            all_indices.extend_from_slice(lod);
+            ranges.push(start..all_indices.len() as u32);
+        }
+        // Short of 0xFFFF, which GLES and WebGL always take as restarting the
+        // primitive.
+        let (index_format, indices) = if self.vertices.len() < u16::MAX as usize {
+            let indices = all_indices.iter().map(|&index| index as u16);
+            let bytes = bytemuck::cast_slice(&indices.collect::<Vec<_>>()).to_vec();
+            (wgpu::IndexFormat::Uint16, bytes)
+        } else {
+            let bytes = bytemuck::cast_slice(&all_indices).to_vec();
+            (wgpu::IndexFormat::Uint32, bytes)
+        };
+        Mesh {
+            name: name.to_string(),
+            vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{:?} Vertex Buffer", name)),
+                contents: bytemuck::cast_slice(&self.vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+            index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{:?} Index Buffer", name)),
+                contents: &indices,
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+            index_format,
+            num_elements: self.indices.len() as u32,
+            lods: ranges,
+            material,
+            unindexed_buffer: None,
+            morph_targets: None,
         }
     }
 }
@@ -152,19 +375,76 @@ pub struct Mesh {
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
+    pub index_format: wgpu::IndexFormat,
     pub num_elements: u32,
+    /// The range of `index_buffer` for each level of detail, the full mesh
+    /// first.
+    pub lods: Vec<Range<u32>>,
     pub material: usize,
+    /// Every triangle with its own vertices, only built by
+    /// `MeshData::create_debug_mesh` when the device can't draw
+    /// `PolygonMode::Line`. See `debug_view::DebugViews`.
+    pub unindexed_buffer: Option<wgpu::Buffer>,
+    pub morph_targets: Option<MorphTargets>,
 }
 
-pub trait DrawModel<'a> {
-    fn draw_mesh(
-        &mut self,
-        mesh: &'a Mesh,
-        material: &'a Material,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    );
+impl Mesh {
+    /// Set the weight of the morph target called `name` for one instance,
+    /// uploaded by `update_morph_weights`.
+    pub fn set_morph_weight(&mut self, instance: usize, name: &str, weight: f32) -> Result<()> {
+        let morph_targets = self
+            .morph_targets
+            .as_mut()
+            .ok_or_else(|| anyhow!("mesh {} has no morph targets", self.name))?;
+        let target = morph_targets
+            .names
+            .iter()
+            .position(|target| target == name)
+            .ok_or_else(|| anyhow!("mesh {} has no morph target {}", self.name, name))?;
+        let weight_slot = morph_targets
+            .weights
+            .get_mut(instance * morph_targets.names.len() + target)
+            .ok_or_else(|| anyhow!("mesh {} has no instance {}", self.name, instance))?;
+        *weight_slot = weight;
+        morph_targets.dirty = true;
+        Ok(())
+    }
 
+    pub fn update_morph_weights(&mut self, queue: &wgpu::Queue) {
+        if let Some(morph_targets) = self.morph_targets.as_mut().filter(|m| m.dirty) {
+            queue.write_buffer(
+                &morph_targets.weight_buffer,
+                0,
+                bytemuck::cast_slice(&morph_targets.weights),
+            );
+            morph_targets.dirty = false;
+        }
+    }
+
+    pub fn create_unindexed_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        vertices: &[ModelVertex],
+        indices: &[u32],
+    ) -> Option<wgpu::Buffer> {
+        if device.features().contains(wgpu::Features::POLYGON_MODE_LINE) {
+            return None;
+        }
+        let unindexed = indices
+            .iter()
+            .map(|&i| vertices[i as usize])
+            .collect::<Vec<_>>();
+        Some(
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(&unindexed),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+        )
+    }
+}
+
+pub trait DrawModel<'a> {
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
@@ -174,13 +454,6 @@ pub trait DrawModel<'a> {
         light_bind_group: &'a wgpu::BindGroup,
     );
 
-    fn draw_model(
-        &mut self,
-        model: &'a Model,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    );
-
     fn draw_model_instanced(
         &mut self,
         model: &'a Model,
@@ -188,31 +461,12 @@ pub trait DrawModel<'a> {
         camera_bind_group: &'a wgpu::BindGroup,
         light_bind_group: &'a wgpu::BindGroup,
     );
-
-    fn draw_model_instanced_with_material(
-        &mut self,
-        model: &'a Model,
-        material: &'a Material,
-        instances: Range<u32>,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    );
 }
 
 impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
 where
     'b: 'a,
 {
-    fn draw_mesh(
-        &mut self,
-        mesh: &'b Mesh,
-        material: &'a Material,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    ) {
-        self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group, light_bind_group);
-    }
-
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'b Mesh,
@@ -222,19 +476,10 @@ where
         _light_bind_group: &'a wgpu::BindGroup,
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
 
-    fn draw_model(
-        &mut self,
-        model: &'b Model,
-        camera_bind_group: &'b wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    ) {
-        self.draw_model_instanced(model, 0..1, camera_bind_group, light_bind_group);
-    }
-
     fn draw_model_instanced(
         &mut self,
         model: &'b Model,
@@ -253,34 +498,9 @@ where
             );
         }
     }
-
-    fn draw_model_instanced_with_material(
-        &mut self,
-        model: &'b Model,
-        material: &'b Material,
-        instances: Range<u32>,
-        camera_bind_group: &'b wgpu::BindGroup,
-        light_bind_group: &'b wgpu::BindGroup,
-    ) {
-        for mesh in &model.meshes {
-            self.draw_mesh_instanced(
-                mesh,
-                material,
-                instances.clone(),
-                camera_bind_group,
-                light_bind_group,
-            );
-        }
-    }
 }
 
 pub trait DrawLight<'a> {
-    fn draw_light_mesh(
-        &mut self,
-        mesh: &'a Mesh,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    );
     fn draw_light_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
@@ -308,15 +528,6 @@ impl<'a, 'b> DrawLight<'b> for wgpu::RenderPass<'a>
 where
     'b: 'a,
 {
-    fn draw_light_mesh(
-        &mut self,
-        mesh: &'b Mesh,
-        camera_bind_group: &'b wgpu::BindGroup,
-        light_bind_group: &'b wgpu::BindGroup,
-    ) {
-        self.draw_light_mesh_instanced(mesh, 0..1, camera_bind_group, light_bind_group);
-    }
-
     fn draw_light_mesh_instanced(
         &mut self,
         mesh: &'b Mesh,
@@ -325,7 +536,7 @@ where
         _light_bind_group: &'b wgpu::BindGroup,
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
         //self.set_bind_group(0, camera_bind_group, &[]);
         //self.set_bind_group(1, light_bind_group, &[]);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
@@ -0,0 +1,142 @@
+use std::f32::consts::PI;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    ibl,
+    model::{self, DrawModel, Vertex},
+    reflect::Reflection,
+    render, resources,
+    shader::Shader,
+    texture,
+    vertex::{Instance, InstanceRaw},
+};
+
+/// A row of instances of a model with morph targets, each cycling through
+/// the targets at its own phase. Meshes without targets aren't drawn.
+pub struct MorphBundle {
+    pub model: model::Model,
+    render_pipeline: wgpu::RenderPipeline,
+    instance_buffer: wgpu::Buffer,
+    num_instances: usize,
+    time: f32,
+}
+
+impl MorphBundle {
+    /// `shader_mtl.wgsl` with the morph targets in place of the rotation,
+    /// reflected along with the other shaders.
+    pub fn shader() -> Shader<'static> {
+        Shader::new(
+            Some("material shader.morph"),
+            include_str!("shader_mtl.wgsl"),
+        )
+        .define("TANGENTS")
+        .define("MORPH_TARGETS")
+        .define_value("CAMERA_GROUP", 1)
+        .define_value("MORPH_GROUP", 2)
+        .define_value("LIGHT_GROUP", 3)
+        .define_value("IBL_GROUP", 3)
+        .define_value("MAX_LOD", ibl::Ibl::PREFILTERED_MIPS - 1)
+    }
+
+    /// Needs storage buffers in vertex shaders, see
+    /// `render::supports_vertex_storage`.
+    pub async fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        reflection: &Reflection,
+        material_layout: &wgpu::BindGroupLayout,
+        file_name: &str,
+        instances: &[Instance],
+    ) -> anyhow::Result<Self> {
+        let shader = Self::shader();
+        let morph_layout = reflection.create_bind_group_layout(
+            device,
+            &["morph_deltas", "morph_weights", "morph"],
+            Some("morph_bind_group_layout"),
+        )?;
+        let model = resources::load_gltf_model(
+            file_name,
+            device,
+            queue,
+            material_layout,
+            Some(&morph_layout),
+            instances.len(),
+        )
+        .await?;
+        let render_pipeline = render::create_render_pipeline(
+            device,
+            &reflection.create_pipeline_layout(device, &shader, Some("Morph Pipeline Layout"))?,
+            config.format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &[model::ModelVertex::desc(), InstanceRaw::desc()],
+            &shader,
+            &render::PipelineOptions::default(),
+            Some("Morph Render Pipeline"),
+        );
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph Instance Buffer"),
+            contents: bytemuck::cast_slice(&instance_data),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        Ok(Self {
+            model,
+            render_pipeline,
+            instance_buffer,
+            num_instances: instances.len(),
+            time: 0.0,
+        })
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: instant::Duration) {
+        self.time += dt.as_secs_f32();
+        for mesh in &mut self.model.meshes {
+            let names = match &mesh.morph_targets {
+                Some(morph_targets) => morph_targets.names.clone(),
+                None => continue,
+            };
+            for instance in 0..self.num_instances {
+                for (i, name) in names.iter().enumerate() {
+                    // Each target in turn, half a cycle apart.
+                    let phase = self.time + instance as f32 * 0.7 + i as f32 * PI;
+                    let weight = (0.5 - 0.5 * phase.cos()).powi(2);
+                    mesh.set_morph_weight(instance, name, weight)
+                        .expect("a weight per target and instance");
+                }
+            }
+            mesh.update_morph_weights(queue);
+        }
+    }
+
+    /// Draw with the camera at group 1 and the image based lighting at
+    /// group 3.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        ibl_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(3, ibl_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        for mesh in &self.model.meshes {
+            let Some(morph_targets) = &mesh.morph_targets else {
+                continue;
+            };
+            let material = &self.model.materials[mesh.material];
+            render_pass.set_bind_group(0, &material.bind_group, &[]);
+            render_pass.set_bind_group(2, &morph_targets.bind_group, &[]);
+            render_pass.draw_mesh_instanced(
+                mesh,
+                material,
+                0..self.num_instances as u32,
+                camera_bind_group,
+                ibl_bind_group,
+            );
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use std::{collections::HashMap, fmt};
+
+use cgmath::{prelude::*, Vector3};
+
+use crate::model::{MeshData, ModelVertex};
+
+/// Vertices in the post-transform cache `CacheStats` simulates, a FIFO like
+/// most desktop GPUs have.
+const FIFO_SIZE: usize = 16;
+
+/// Vertices in the LRU cache `optimize_vertex_cache` scores against.
+const LRU_SIZE: usize = 32;
+
+/// How well the post-transform vertex cache is used by a mesh's indices.
+#[derive(Copy, Clone, Debug)]
+pub struct CacheStats {
+    /// Average cache miss ratio, vertices transformed per triangle. 0.5 is
+    /// the best possible for a large grid and 3 the worst.
+    pub acmr: f32,
+    /// Average transform to vertex ratio, how many times each vertex is
+    /// transformed. 1 is the best possible.
+    pub atvr: f32,
+}
+
+impl CacheStats {
+    pub fn analyze(indices: &[u32], num_vertices: usize) -> Self {
+        let mut cache = std::collections::VecDeque::with_capacity(FIFO_SIZE);
+        let mut used = vec![false; num_vertices];
+        let mut misses = 0;
+        for &index in indices {
+            used[index as usize] = true;
+            if !cache.contains(&index) {
+                misses += 1;
+                if cache.len() == FIFO_SIZE {
+                    cache.pop_front();
+                }
+                cache.push_back(index);
+            }
+        }
+        let triangles = (indices.len() / 3).max(1);
+        let vertices = used.iter().filter(|&&used| used).count().max(1);
+        Self {
+            acmr: misses as f32 / triangles as f32,
+            atvr: misses as f32 / vertices as f32,
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ACMR {:.3}, ATVR {:.3}", self.acmr, self.atvr)
+    }
+}
+
+/// Every step below in turn, logging the cache statistics before and after.
+/// Returns the optimized mesh and, for each of its vertices, the vertex of
+/// `data` it came from.
+pub fn optimize(data: &MeshData, name: &str) -> (MeshData, Vec<u32>) {
+    let before = CacheStats::analyze(&data.indices, data.vertices.len());
+    let (mut optimized, welded) = weld(data);
+    optimize_vertex_cache(&mut optimized.indices, optimized.vertices.len());
+    optimize_overdraw(&mut optimized.indices, &optimized.vertices);
+    let origins = optimize_vertex_fetch(&mut optimized)
+        .into_iter()
+        .map(|index| welded[index as usize])
+        .collect();
+    let after = CacheStats::analyze(&optimized.indices, optimized.vertices.len());
+    log::info!(
+        "{}: {} vertices, {} before and {} vertices, {} after optimizing",
+        name,
+        data.vertices.len(),
+        before,
+        optimized.vertices.len(),
+        after,
+    );
+    (optimized, origins)
+}
+
+/// Merge vertices that are identical to the bit. Returns the welded mesh and,
+/// for each of its vertices, the first vertex of `data` that was merged.
+pub fn weld(data: &MeshData) -> (MeshData, Vec<u32>) {
+    let mut welded = MeshData::default();
+    let mut origins = Vec::new();
+    let mut unique = HashMap::new();
+    for &index in &data.indices {
+        let vertex = data.vertices[index as usize];
+        let key: [u32; 12] = bytemuck::cast(vertex);
+        let new_index = *unique.entry(key).or_insert_with(|| {
+            welded.vertices.push(vertex);
+            origins.push(index);
+            welded.vertices.len() as u32 - 1
+        });
+        welded.indices.push(new_index);
+    }
+    (welded, origins)
+}
+
+/// How much drawing a vertex next is worth, following Tom Forsyth's "Linear-
+/// Speed Vertex Cache Optimisation". Vertices just used and vertices with few
+/// triangles left score highest.
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache = match cache_position {
+        // The triangle just drawn, which a new one can't share all of.
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (LRU_SIZE - 3) as f32).powf(1.5),
+        None => 0.0,
+    };
+    cache + 2.0 * (remaining as f32).powf(-0.5)
+}
+
+/// Reorder triangles so that they reuse recently transformed vertices.
+pub fn optimize_vertex_cache(indices: &mut [u32], num_vertices: usize) {
+    let num_triangles = indices.len() / 3;
+    let mut triangles_of = vec![Vec::new(); num_vertices];
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for &index in corners {
+            triangles_of[index as usize].push(triangle);
+        }
+    }
+
+    let mut cache_position = vec![None; num_vertices];
+    let mut vertex_scores = (0..num_vertices)
+        .map(|v| vertex_score(None, triangles_of[v].len()))
+        .collect::<Vec<_>>();
+    let triangle_score = |scores: &[f32], triangle: usize| -> f32 {
+        indices[triangle * 3..triangle * 3 + 3]
+            .iter()
+            .map(|&index| scores[index as usize])
+            .sum()
+    };
+    let mut drawn = vec![false; num_triangles];
+    let mut cache = Vec::<u32>::with_capacity(LRU_SIZE + 3);
+    let mut order = Vec::with_capacity(indices.len());
+    // Where to look for an undrawn triangle when none touch the cache.
+    let mut next_undrawn = 0;
+    let mut best = None;
+
+    while order.len() < indices.len() {
+        let triangle = match best {
+            Some(triangle) => triangle,
+            None => {
+                while drawn[next_undrawn] {
+                    next_undrawn += 1;
+                }
+                next_undrawn
+            }
+        };
+        drawn[triangle] = true;
+        let corners = [
+            indices[triangle * 3],
+            indices[triangle * 3 + 1],
+            indices[triangle * 3 + 2],
+        ];
+        order.extend_from_slice(&corners);
+
+        for &index in &corners {
+            triangles_of[index as usize].retain(|&t| t != triangle);
+            cache.retain(|&cached| cached != index);
+        }
+        let evicted = cache.len().saturating_sub(LRU_SIZE - 3);
+        for &index in &cache[cache.len() - evicted..] {
+            cache_position[index as usize] = None;
+        }
+        let evicted = cache.split_off(cache.len() - evicted);
+        cache.splice(0..0, corners);
+        for (position, &index) in cache.iter().enumerate() {
+            cache_position[index as usize] = Some(position);
+        }
+
+        for &index in cache.iter().chain(&evicted) {
+            let v = index as usize;
+            vertex_scores[v] = vertex_score(cache_position[v], triangles_of[v].len());
+        }
+        best = None;
+        let mut best_score = f32::MIN;
+        for &index in &cache {
+            for &t in &triangles_of[index as usize] {
+                let score = triangle_score(&vertex_scores, t);
+                if score > best_score {
+                    best = Some(t);
+                    best_score = score;
+                }
+            }
+        }
+    }
+    indices.copy_from_slice(&order);
+}
+
+/// Reorder the clusters of triangles `optimize_vertex_cache` made so that
+/// those facing out from the middle of the mesh are drawn first, where they
+/// can hide the rest. Clusters start wherever a triangle shares no vertex
+/// with the cache, so the cache is used as well as before.
+pub fn optimize_overdraw(indices: &mut [u32], vertices: &[ModelVertex]) {
+    let position = |index: u32| Vector3::from(vertices[index as usize].position);
+
+    let mut starts = Vec::new();
+    let mut cache = std::collections::VecDeque::with_capacity(FIFO_SIZE);
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        if corners.iter().all(|index| !cache.contains(index)) {
+            starts.push(triangle * 3);
+        }
+        for &index in corners {
+            if !cache.contains(&index) {
+                if cache.len() == FIFO_SIZE {
+                    cache.pop_front();
+                }
+                cache.push_back(index);
+            }
+        }
+    }
+    if starts.len() < 2 {
+        return;
+    }
+    starts.push(indices.len());
+
+    let middle = indices
+        .iter()
+        .fold(Vector3::zero(), |sum, &index| sum + position(index))
+        / indices.len() as f32;
+    let mut clusters = starts
+        .windows(2)
+        .map(|range| {
+            let (mut centroid, mut normal, mut area) = (Vector3::zero(), Vector3::zero(), 0.0);
+            for corners in indices[range[0]..range[1]].chunks_exact(3) {
+                let [a, b, c] = [corners[0], corners[1], corners[2]].map(position);
+                // Twice the area, pointing out of the front face.
+                let cross = (b - a).cross(c - a);
+                let weight = cross.magnitude();
+                centroid += (a + b + c) / 3.0 * weight;
+                normal += cross;
+                area += weight;
+            }
+            let facing = if area > 0.0 && normal.magnitude2() > 0.0 {
+                (centroid / area - middle).dot(normal.normalize())
+            } else {
+                f32::MIN
+            };
+            (facing, range[0]..range[1])
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let order = clusters
+        .into_iter()
+        .flat_map(|(_, range)| indices[range].to_vec())
+        .collect::<Vec<_>>();
+    indices.copy_from_slice(&order);
+}
+
+/// Renumber vertices in the order the indices first use them, dropping any
+/// that aren't used. Returns the old index of each vertex.
+pub fn optimize_vertex_fetch(data: &mut MeshData) -> Vec<u32> {
+    let mut remap = vec![None; data.vertices.len()];
+    let mut origins = Vec::new();
+    for index in &mut data.indices {
+        *index = *remap[*index as usize].get_or_insert_with(|| {
+            origins.push(*index);
+            origins.len() as u32 - 1
+        });
+    }
+    data.vertices = origins
+        .iter()
+        .map(|&index| data.vertices[index as usize])
+        .collect();
+    origins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives;
+
+    /// A grid with its triangles scattered, so the cache is little use.
+    fn shuffled_grid() -> MeshData {
+        let grid = primitives::plane([1.0, 1.0], [32, 32]);
+        let triangles = grid.indices.chunks_exact(3).collect::<Vec<_>>();
+        // 7919 shares no factor with the 2048 triangles.
+        let indices = (0..triangles.len())
+            .flat_map(|t| triangles[t * 7919 % triangles.len()].to_vec())
+            .collect();
+        MeshData {
+            vertices: grid.vertices,
+            indices,
+        }
+    }
+
+    fn bits(vertex: ModelVertex) -> [u32; 12] {
+        bytemuck::cast(vertex)
+    }
+
+    /// Each triangle as the bits of its corners, in a canonical order.
+    fn triangles(data: &MeshData) -> Vec<[[u32; 12]; 3]> {
+        let mut triangles = data
+            .indices
+            .chunks_exact(3)
+            .map(|corners| {
+                let [a, b, c] = [corners[0], corners[1], corners[2]]
+                    .map(|index| bits(data.vertices[index as usize]));
+                // Rotated to start at the least corner, keeping the winding.
+                [[a, b, c], [b, c, a], [c, a, b]].into_iter().min().unwrap()
+            })
+            .collect::<Vec<_>>();
+        triangles.sort();
+        triangles
+    }
+
+    fn assert_origins(data: &MeshData, result: &MeshData, origins: &[u32]) {
+        assert_eq!(origins.len(), result.vertices.len());
+        for (vertex, &origin) in result.vertices.iter().zip(origins) {
+            assert_eq!(bits(*vertex), bits(data.vertices[origin as usize]));
+        }
+    }
+
+    #[test]
+    fn vertex_cache() {
+        let data = shuffled_grid();
+        let before = CacheStats::analyze(&data.indices, data.vertices.len());
+        let mut optimized = data.clone();
+        optimize_vertex_cache(&mut optimized.indices, optimized.vertices.len());
+        let after = CacheStats::analyze(&optimized.indices, optimized.vertices.len());
+        assert!(before.acmr > 2.0, "{}", before);
+        assert!(after.acmr < 1.0, "{}", after);
+        assert_eq!(triangles(&optimized), triangles(&data));
+    }
+
+    #[test]
+    fn overdraw() {
+        let mut data = primitives::cube(1.0, 4);
+        optimize_vertex_cache(&mut data.indices, data.vertices.len());
+        let before = CacheStats::analyze(&data.indices, data.vertices.len());
+        let mut optimized = data.clone();
+        optimize_overdraw(&mut optimized.indices, &optimized.vertices);
+        let after = CacheStats::analyze(&optimized.indices, optimized.vertices.len());
+        assert!(after.acmr <= before.acmr * 1.1, "{} then {}", before, after);
+        assert_eq!(triangles(&optimized), triangles(&data));
+    }
+
+    #[test]
+    fn welded() {
+        // A second copy of every vertex, used by every other triangle.
+        let mut data = shuffled_grid();
+        let num_vertices = data.vertices.len() as u32;
+        data.vertices.extend_from_within(..);
+        for (i, index) in data.indices.iter_mut().enumerate() {
+            if i / 3 % 2 == 1 {
+                *index += num_vertices;
+            }
+        }
+        let (welded, origins) = weld(&data);
+        assert_eq!(welded.vertices.len(), num_vertices as usize);
+        assert_origins(&data, &welded, &origins);
+        assert_eq!(triangles(&welded), triangles(&data));
+    }
+
+    #[test]
+    fn vertex_fetch() {
+        let mut data = shuffled_grid();
+        // Left unused.
+        data.vertices.push(data.vertices[0]);
+        let mut fetched = data.clone();
+        let origins = optimize_vertex_fetch(&mut fetched);
+        assert_eq!(fetched.vertices.len(), data.vertices.len() - 1);
+        assert_origins(&data, &fetched, &origins);
+        assert_eq!(triangles(&fetched), triangles(&data));
+        // Each vertex is first used after the ones before it.
+        let mut next = 0;
+        for &index in &fetched.indices {
+            assert!(index <= next);
+            next = next.max(index + 1);
+        }
+    }
+
+    #[test]
+    fn optimized() {
+        let data = shuffled_grid();
+        let (optimized, origins) = optimize(&data, "grid");
+        assert_origins(&data, &optimized, &origins);
+        assert_eq!(triangles(&optimized), triangles(&data));
+        let before = CacheStats::analyze(&data.indices, data.vertices.len());
+        let after = CacheStats::analyze(&optimized.indices, optimized.vertices.len());
+        assert!(after.acmr < before.acmr, "{} then {}", before, after);
+    }
+}
@@ -0,0 +1,348 @@
+use cgmath::{prelude::*, Point3, Rad, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera,
+    reflect::Reflection,
+    render::{self, ComputePass},
+    shader::Shader,
+    texture,
+};
+
+/// Entries of `EmitterUniform::colors`.
+const COLOR_STOPS: usize = 16;
+
+/// One particle as stored by `particles_update.wgsl`, and read back as an
+/// instance by `particles.wgsl`. Dead once `age` reaches `lifetime`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Particle {
+    pub position: [f32; 3],
+    pub age: f32,
+    pub velocity: [f32; 3],
+    pub lifetime: f32,
+    pub color: [f32; 4],
+}
+
+impl Particle {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem::size_of;
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<Particle>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct EmitterUniform {
+    position: [f32; 3],
+    /// First slot to respawn this frame.
+    spawn_start: u32,
+    direction: [f32; 3],
+    cos_cone: f32,
+    gravity: [f32; 3],
+    speed: f32,
+    camera_right: [f32; 3],
+    lifetime: f32,
+    camera_up: [f32; 3],
+    size: f32,
+    dt: f32,
+    spawn_count: u32,
+    seed: u32,
+    capacity: u32,
+    colors: [[f32; 4]; COLOR_STOPS],
+}
+
+/// Where and how particles are spawned, and how they move and fade.
+pub struct Emitter {
+    pub position: Point3<f32>,
+    /// Axis of the cone particles leave in.
+    pub direction: Vector3<f32>,
+    /// Angle between the axis and the cone's side.
+    pub cone: Rad<f32>,
+    pub speed: f32,
+    /// Particles per second.
+    pub spawn_rate: f32,
+    /// Longest a particle lives, in seconds.
+    pub lifetime: f32,
+    pub gravity: Vector3<f32>,
+    /// Half the width of a particle.
+    pub size: f32,
+    /// Color over life, from birth at 0 to death at 1.
+    pub gradient: colorgrad::Gradient,
+}
+
+impl Emitter {
+    /// Enough particles for every one to live out its lifetime.
+    fn capacity(&self) -> u32 {
+        ((self.spawn_rate * self.lifetime).ceil() as u32).max(1)
+    }
+}
+
+/// Particles from one `Emitter`, simulated by a compute shader and drawn as
+/// camera-facing billboards with additive blending.
+///
+/// Needs compute shaders, see `render::supports_compute`.
+pub struct ParticleSystem {
+    pub emitter: Emitter,
+    capacity: u32,
+    uniform: EmitterUniform,
+    uniform_buffer: wgpu::Buffer,
+    particle_buffer: wgpu::Buffer,
+    emitter_bind_group: wgpu::BindGroup,
+    particles_bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+    workgroup_size: [u32; 3],
+    render_pipeline: wgpu::RenderPipeline,
+    /// Fractional particles carried over to the next frame.
+    spawn_accumulator: f32,
+}
+
+impl ParticleSystem {
+    /// The compute and the render shader, reflected along with the other
+    /// shaders so that the camera bind group can be shared.
+    pub fn shaders() -> [Shader<'static>; 2] {
+        [
+            Shader::new(
+                Some("particles_update.shader"),
+                include_str!("particles_update.wgsl"),
+            )
+            .define_value("PARTICLES_GROUP", 0)
+            .define_value("EMITTER_GROUP", 1),
+            Shader::new(Some("particles.shader"), include_str!("particles.wgsl"))
+                .define_value("CAMERA_GROUP", 0)
+                .define_value("EMITTER_GROUP", 1),
+        ]
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        reflection: &Reflection,
+        emitter: Emitter,
+    ) -> anyhow::Result<Self> {
+        let [update_shader, shader] = Self::shaders();
+        reflection.check_sizes(&[("emitter", std::mem::size_of::<EmitterUniform>())])?;
+
+        // The particle count is fixed, so the emitter's rate and lifetime
+        // can only be lowered after this.
+        let capacity = emitter.capacity();
+        let particle_buffer = render::create_storage_buffer(
+            device,
+            &vec![Particle::default(); capacity as usize],
+            wgpu::BufferUsages::VERTEX,
+            Some("particles.particle_buffer"),
+        );
+        let uniform = EmitterUniform {
+            position: [0.0; 3],
+            spawn_start: 0,
+            direction: [0.0, 1.0, 0.0],
+            cos_cone: 1.0,
+            gravity: [0.0; 3],
+            speed: 0.0,
+            camera_right: [1.0, 0.0, 0.0],
+            lifetime: 0.0,
+            camera_up: [0.0, 1.0, 0.0],
+            size: 0.0,
+            dt: 0.0,
+            spawn_count: 0,
+            seed: 0,
+            capacity,
+            colors: [[0.0; 4]; COLOR_STOPS],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("particles.uniform_buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let emitter_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &reflection.create_bind_group_layout(
+                device,
+                &["emitter"],
+                Some("particles.emitter_layout"),
+            )?,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("particles.emitter_bind_group"),
+        });
+        let particles_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &reflection.create_bind_group_layout(
+                device,
+                &["particles"],
+                Some("particles.particles_layout"),
+            )?,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: particle_buffer.as_entire_binding(),
+            }],
+            label: Some("particles.particles_bind_group"),
+        });
+
+        let compute_pipeline = render::create_compute_pipeline(
+            device,
+            &reflection.create_pipeline_layout(device, &update_shader, update_shader.label())?,
+            &update_shader,
+            Some("particles.compute_pipeline"),
+        )?;
+        let workgroup_size = reflection.workgroup_size(&update_shader)?;
+        let render_pipeline = render::create_render_pipeline(
+            device,
+            &reflection.create_pipeline_layout(device, &shader, shader.label())?,
+            config.format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &[Particle::desc()],
+            &shader,
+            &render::PipelineOptions {
+                cull_mode: None,
+                depth_write_enabled: false,
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::Zero,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                }),
+                ..Default::default()
+            },
+            Some("particles.render_pipeline"),
+        );
+
+        Ok(Self {
+            emitter,
+            capacity,
+            uniform,
+            uniform_buffer,
+            particle_buffer,
+            emitter_bind_group,
+            particles_bind_group,
+            compute_pipeline,
+            workgroup_size,
+            render_pipeline,
+            spawn_accumulator: 0.0,
+        })
+    }
+
+    /// Work out what to spawn this frame and face the particles to `camera`,
+    /// ready for `update` to upload.
+    pub fn advance(&mut self, dt: instant::Duration, camera: &Camera) {
+        let dt = dt.as_secs_f32();
+        let emitter = &self.emitter;
+
+        self.spawn_accumulator += emitter.spawn_rate * dt;
+        let spawn_count = (self.spawn_accumulator.floor() as u32).min(self.capacity);
+        self.spawn_accumulator -= spawn_count as f32;
+        // Carry on from the slots spawned last frame, which are the oldest.
+        let spawn_start = (self.uniform.spawn_start + self.uniform.spawn_count) % self.capacity;
+
+        let view = camera.calc_matrix();
+        let mut colors = [[0.0; 4]; COLOR_STOPS];
+        for (i, color) in colors.iter_mut().enumerate() {
+            let c = emitter.gradient.at(i as f64 / (COLOR_STOPS - 1) as f64);
+            *color = [c.r as f32, c.g as f32, c.b as f32, c.a as f32];
+        }
+        self.uniform = EmitterUniform {
+            position: emitter.position.into(),
+            spawn_start,
+            direction: emitter.direction.normalize().into(),
+            cos_cone: emitter.cone.cos(),
+            gravity: emitter.gravity.into(),
+            speed: emitter.speed,
+            camera_right: [view.x.x, view.y.x, view.z.x],
+            lifetime: emitter.lifetime,
+            camera_up: [view.x.y, view.y.y, view.z.y],
+            size: emitter.size,
+            dt,
+            spawn_count,
+            seed: self.uniform.seed.wrapping_add(1),
+            capacity: self.capacity,
+            colors,
+        };
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.emitter_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.particle_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.capacity);
+    }
+}
+
+impl ComputePass for ParticleSystem {
+    fn update(&mut self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    /// Advance the particles by the `dt` given to `advance`.
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("particles.compute_pass"),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.particles_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.emitter_bind_group, &[]);
+        let [x, y, z] = render::dispatch_size([self.capacity, 1, 1], self.workgroup_size);
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+}
+
+/// A fountain of sparks, from yellow through red to transparent.
+pub fn sparks(position: Point3<f32>) -> anyhow::Result<Emitter> {
+    Ok(Emitter {
+        position,
+        direction: Vector3::unit_y(),
+        cone: Rad(0.35),
+        speed: 6.0,
+        spawn_rate: 400.0,
+        lifetime: 2.0,
+        gravity: Vector3::new(0.0, -9.8, 0.0),
+        size: 0.06,
+        gradient: colorgrad::CustomGradient::new()
+            .colors(&[
+                colorgrad::Color::new(1.0, 0.95, 0.6, 1.0),
+                colorgrad::Color::new(1.0, 0.5, 0.1, 1.0),
+                colorgrad::Color::new(0.8, 0.1, 0.05, 0.0),
+            ])
+            .build()?,
+    })
+}
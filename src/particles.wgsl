@@ -0,0 +1,52 @@
+// Particles as camera-facing quads, one instance per particle, added onto
+// what is behind them.
+#include "camera.wgsl"
+#include "emitter.wgsl"
+
+// Matches `particles::Particle::desc()`.
+struct ParticleInput {
+    @location(0) position: vec3<f32>,
+    @location(1) age: f32,
+    @location(2) lifetime: f32,
+    @location(3) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    particle: ParticleInput,
+) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    var out: VertexOutput;
+    out.corner = corner;
+    out.color = particle.color;
+    if (particle.age >= particle.lifetime) {
+        // Dead, so outside the clip volume.
+        out.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        return out;
+    }
+    let offset = (emitter.camera_right * corner.x + emitter.camera_up * corner.y) * emitter.size;
+    out.clip_position = camera.view_proj * vec4<f32>(particle.position + offset, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let falloff = 1.0 - smoothstep(0.25, 1.0, length(in.corner));
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
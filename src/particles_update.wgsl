@@ -0,0 +1,72 @@
+// Advances every particle by a frame, respawning those in the ring of slots
+// `emitter.spawn_start` onwards.
+#include "emitter.wgsl"
+
+// Matches `particles::Particle`.
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+    color: vec4<f32>,
+}
+@group(PARTICLES_GROUP) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+
+let PI: f32 = 3.14159265;
+
+// https://www.pcg-random.org/
+fn hash(x: u32) -> u32 {
+    let state = x * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform in [0, 1], different for each particle, frame and `n`.
+fn random(index: u32, n: u32) -> f32 {
+    return f32(hash(hash(index * 8u + n) ^ emitter.seed)) / 4294967295.0;
+}
+
+// A direction within the emitter's cone.
+fn random_direction(index: u32) -> vec3<f32> {
+    let axis = normalize(emitter.direction);
+    var other = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(axis.x) > 0.9) {
+        other = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let tangent = normalize(cross(axis, other));
+    let bitangent = cross(axis, tangent);
+    let cos_theta = mix(emitter.cos_cone, 1.0, random(index, 0u));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let phi = 2.0 * PI * random(index, 1u);
+    return (tangent * cos(phi) + bitangent * sin(phi)) * sin_theta + axis * cos_theta;
+}
+
+fn color_over_life(t: f32) -> vec4<f32> {
+    let x = clamp(t, 0.0, 1.0) * 15.0;
+    let i = u32(floor(x));
+    let j = min(i + 1u, 15u);
+    return mix(emitter.colors[i], emitter.colors[j], fract(x));
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= emitter.capacity) {
+        return;
+    }
+    var particle = particles[index];
+    let slot = (index + emitter.capacity - emitter.spawn_start) % emitter.capacity;
+    if (slot < emitter.spawn_count) {
+        particle.position = emitter.position;
+        particle.velocity = random_direction(index) * emitter.speed * mix(0.75, 1.25, random(index, 2u));
+        particle.age = 0.0;
+        particle.lifetime = emitter.lifetime * mix(0.5, 1.0, random(index, 3u));
+    } else if (particle.age < particle.lifetime) {
+        particle.velocity += emitter.gravity * emitter.dt;
+        particle.position += particle.velocity * emitter.dt;
+        particle.age += emitter.dt;
+    }
+    particle.color = color_over_life(particle.age / max(particle.lifetime, 0.0001));
+    particles[index] = particle;
+}
@@ -0,0 +1,330 @@
+use std::{collections::HashMap, f32::consts::PI, ops::Range};
+
+use cgmath::{prelude::*, Vector3};
+
+use crate::model::{MeshData, ModelVertex};
+
+/// How far apart the partial derivatives of the surfaces here are taken, in
+/// texture coordinates.
+const STEP: f32 = 1e-3;
+
+/// A grid of quads over the texture coordinates `u` by `v`, both within 0
+/// to 1, placed by `surface`. Normals and tangents come from `surface`'s
+/// partial derivatives, taken `du` and `dv` apart, so they agree wherever two
+/// grids meet. The normal is `dv` cross `du`, which faces the viewer when `u`
+/// runs right and `v` down.
+pub fn parametric(
+    surface: impl Fn(f32, f32) -> Vector3<f32>,
+    u: Range<f32>,
+    v: Range<f32>,
+    [columns, rows]: [u32; 2],
+    [du, dv]: [f32; 2],
+) -> MeshData {
+    let mut vertices = Vec::with_capacity(((columns + 1) * (rows + 1)) as usize);
+    for row in 0..=rows {
+        let v = v.start + (v.end - v.start) * row as f32 / rows as f32;
+        // Pulled in from the edges, where a pole or the tip of a cone would
+        // have no tangent.
+        let v_d = v.clamp(dv, 1.0 - dv);
+        for column in 0..=columns {
+            let u = u.start + (u.end - u.start) * column as f32 / columns as f32;
+            let d_du = (surface(u + du, v_d) - surface(u - du, v_d)) / (2.0 * du);
+            let d_dv = (surface(u, v_d + dv) - surface(u, v_d - dv)) / (2.0 * dv);
+            let normal = d_dv.cross(d_du).normalize();
+            let tangent = (d_du - normal * normal.dot(d_du)).normalize();
+            vertices.push(vertex(surface(u, v), [u, v], normal, tangent));
+        }
+    }
+
+    let mut indices = Vec::with_capacity((columns * rows * 6) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            let a = row * (columns + 1) + column;
+            let (b, c) = (a + 1, a + columns + 1);
+            indices.extend_from_slice(&[a, c, b, b, c, c + 1]);
+        }
+    }
+    MeshData { vertices, indices }
+}
+
+fn vertex(
+    position: Vector3<f32>,
+    tex_coords: [f32; 2],
+    normal: Vector3<f32>,
+    tangent: Vector3<f32>,
+) -> ModelVertex {
+    ModelVertex {
+        position: position.into(),
+        tex_coords,
+        normal: normal.into(),
+        // The bitangent, `normal` cross `tangent`, points up the texture
+        // like MikkTSpace's, against `v`.
+        tangent: tangent.extend(1.0).into(),
+    }
+}
+
+/// The direction at `u` around the y axis, with `u` = 0 facing -x and
+/// `u` = 0.25 facing +z like the texture coordinates of `earth.obj`.
+pub fn around(u: f32) -> Vector3<f32> {
+    let phi = 2.0 * PI * u;
+    Vector3::new(-phi.cos(), 0.0, phi.sin())
+}
+
+/// A flat grid in the xz plane facing +y, with `v` running along +z.
+pub fn plane(size: [f32; 2], subdivisions: [u32; 2]) -> MeshData {
+    parametric(
+        |u, v| Vector3::new((u - 0.5) * size[0], 0.0, (v - 0.5) * size[1]),
+        0.0..1.0,
+        0.0..1.0,
+        subdivisions,
+        [STEP; 2],
+    )
+}
+
+/// A flat fan in the xy plane facing +z, with a triangle from the origin to
+/// each pair of neighbouring points along `outline`, counterclockwise. The
+/// texture covers -0.5 to 0.5 the right way up.
+pub fn fan(outline: &[[f32; 2]]) -> MeshData {
+    let vertices = std::iter::once([0.0; 2])
+        .chain(outline.iter().copied())
+        .map(|[x, y]| {
+            vertex(
+                Vector3::new(x, y, 0.0),
+                [x + 0.5, 0.5 - y],
+                Vector3::unit_z(),
+                Vector3::unit_x(),
+            )
+        })
+        .collect();
+    let indices = (1..outline.len() as u32)
+        .flat_map(|i| [0, i, i + 1])
+        .collect();
+    MeshData { vertices, indices }
+}
+
+/// A cube centered on the origin, with each face a grid textured the right
+/// way up.
+pub fn cube(size: f32, subdivisions: u32) -> MeshData {
+    let faces = [
+        (Vector3::unit_x(), -Vector3::unit_z()),
+        (-Vector3::unit_x(), Vector3::unit_z()),
+        (Vector3::unit_y(), Vector3::unit_x()),
+        (-Vector3::unit_y(), Vector3::unit_x()),
+        (Vector3::unit_z(), Vector3::unit_x()),
+        (-Vector3::unit_z(), -Vector3::unit_x()),
+    ];
+    let mut data = MeshData::default();
+    for (normal, right) in faces {
+        let down = right.cross(normal);
+        data.append(&parametric(
+            |u, v| (normal * 0.5 + right * (u - 0.5) + down * (v - 0.5)) * size,
+            0.0..1.0,
+            0.0..1.0,
+            [subdivisions; 2],
+            [STEP; 2],
+        ));
+    }
+    data
+}
+
+/// A sphere of `segments` around and `rings` from pole to pole.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+    parametric(
+        |u, v| {
+            let theta = PI * v;
+            around(u) * theta.sin() * radius + Vector3::unit_y() * theta.cos() * radius
+        },
+        0.0..1.0,
+        0.0..1.0,
+        [segments, rings],
+        [STEP; 2],
+    )
+}
+
+/// An icosahedron with each triangle split into four `subdivisions` times,
+/// pushed out onto the sphere. Textured like `uv_sphere`.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut positions = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .map(|p| Vector3::from(p).normalize())
+    .to_vec();
+    let mut triangles = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Shared by the two triangles on either side of an edge.
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let p = (positions[a as usize] + positions[b as usize]).normalize();
+                positions.push(p);
+                positions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // The texture coordinates aren't shared across the seam or at the poles,
+    // so vertices are split wherever `u` differs.
+    let mut data = MeshData::default();
+    let mut split = HashMap::new();
+    for triangle in &triangles {
+        let mut uvs = triangle.map(|i| {
+            let p = positions[i as usize];
+            let u = (p.z.atan2(-p.x) / (2.0 * PI)).rem_euclid(1.0);
+            [u, p.y.clamp(-1.0, 1.0).acos() / PI]
+        });
+        let us = uvs.map(|[u, _]| u);
+        if us.iter().cloned().fold(0.0, f32::max) - us.iter().cloned().fold(1.0, f32::min) > 0.5 {
+            for [u, _] in &mut uvs {
+                if *u < 0.5 {
+                    *u += 1.0;
+                }
+            }
+        }
+        // A pole takes the `u` between its neighbours.
+        for i in 0..3 {
+            if positions[triangle[i] as usize].y.abs() > 1.0 - 1e-6 {
+                uvs[i][0] = (uvs[(i + 1) % 3][0] + uvs[(i + 2) % 3][0]) / 2.0;
+            }
+        }
+
+        for (&i, uv) in triangle.iter().zip(uvs) {
+            let index = *split.entry((i, uv[0].to_bits())).or_insert_with(|| {
+                let normal = positions[i as usize];
+                let phi = 2.0 * PI * uv[0];
+                let tangent = Vector3::new(phi.sin(), 0.0, phi.cos());
+                data.vertices
+                    .push(vertex(normal * radius, uv, normal, tangent));
+                data.vertices.len() as u32 - 1
+            });
+            data.indices.push(index);
+        }
+    }
+    data
+}
+
+/// A flat disk facing +y when `up` is set and -y otherwise, textured in polar
+/// coordinates with `v` running out from the center.
+fn disk(y: f32, radius: f32, segments: u32, up: bool) -> MeshData {
+    parametric(
+        |u, v| {
+            let rho = if up { v } else { 1.0 - v };
+            around(u) * rho * radius + Vector3::unit_y() * y
+        },
+        0.0..1.0,
+        0.0..1.0,
+        [segments, 1],
+        [STEP; 2],
+    )
+}
+
+/// An upright cylinder centered on the origin, capped at both ends.
+pub fn cylinder(radius: f32, height: f32, segments: u32, rings: u32) -> MeshData {
+    let mut data = parametric(
+        |u, v| around(u) * radius + Vector3::unit_y() * (0.5 - v) * height,
+        0.0..1.0,
+        0.0..1.0,
+        [segments, rings],
+        [STEP; 2],
+    );
+    data.append(&disk(height / 2.0, radius, segments, true));
+    data.append(&disk(-height / 2.0, radius, segments, false));
+    data
+}
+
+/// An upright cone centered on the origin with its tip at the top, capped at
+/// the base.
+pub fn cone(radius: f32, height: f32, segments: u32, rings: u32) -> MeshData {
+    let mut data = parametric(
+        |u, v| around(u) * v * radius + Vector3::unit_y() * (0.5 - v) * height,
+        0.0..1.0,
+        0.0..1.0,
+        [segments, rings],
+        [STEP; 2],
+    );
+    data.append(&disk(-height / 2.0, radius, segments, false));
+    data
+}
+
+/// A ring around the y axis, `radius` out to the middle of a tube
+/// `tube_radius` thick. `segments` go around the ring and `sides` around the
+/// tube.
+pub fn torus(radius: f32, tube_radius: f32, segments: u32, sides: u32) -> MeshData {
+    parametric(
+        |u, v| {
+            let psi = 2.0 * PI * v;
+            around(u) * (radius + tube_radius * psi.cos())
+                - Vector3::unit_y() * tube_radius * psi.sin()
+        },
+        0.0..1.0,
+        0.0..1.0,
+        [segments, sides],
+        [STEP; 2],
+    )
+}
+
+/// An upright cylinder with hemispheres on its ends, `height` apart. The
+/// `rings` from pole to pole are spread evenly along its length.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> MeshData {
+    let quarter = PI / 2.0 * radius;
+    let length = 2.0 * quarter + height;
+    parametric(
+        |u, v| {
+            let s = v * length;
+            let (ring, y) = if s < quarter {
+                let theta = s / radius;
+                (theta.sin() * radius, height / 2.0 + theta.cos() * radius)
+            } else if s < quarter + height {
+                (radius, height / 2.0 - (s - quarter))
+            } else {
+                let theta = PI / 2.0 + (s - quarter - height) / radius;
+                (theta.sin() * radius, -height / 2.0 + theta.cos() * radius)
+            };
+            around(u) * ring + Vector3::unit_y() * y
+        },
+        0.0..1.0,
+        0.0..1.0,
+        [segments, rings],
+        [STEP; 2],
+    )
+}
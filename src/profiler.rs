@@ -0,0 +1,273 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use instant::{Duration, Instant};
+
+/// Samples in the rolling averages.
+const WINDOW: usize = 60;
+/// Frames kept for `chrome_trace`.
+const TRACE_FRAMES: usize = 120;
+/// Timed GPU passes per frame.
+const MAX_PASSES: u32 = 16;
+
+/// Mean of the most recent `WINDOW` samples.
+#[derive(Default)]
+struct Rolling {
+    samples: VecDeque<Duration>,
+}
+
+impl Rolling {
+    fn push(&mut self, sample: Duration) {
+        if self.samples.len() == WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn mean(&self) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        self.samples.iter().sum::<Duration>() / self.samples.len() as u32
+    }
+}
+
+/// A complete event in the Chrome trace format, in microseconds.
+struct TraceEvent {
+    name: &'static str,
+    gpu: bool,
+    start: f64,
+    duration: f64,
+}
+
+/// Timestamp queries written on the command encoder around whole passes.
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    readback_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Passes written into the frame being recorded.
+    passes: Vec<&'static str>,
+    /// Whether the last pass begun still needs its end timestamp.
+    open: bool,
+    /// Passes whose timestamps are in `readback_buffer`, waiting to be mapped.
+    pending: Vec<&'static str>,
+    /// Whether `readback_buffer` has been asked to map.
+    requested: bool,
+    mapped: Arc<AtomicBool>,
+    /// The first timestamp seen, so that trace times start near zero.
+    origin: Option<u64>,
+}
+
+/// CPU timings of `State::update`/`State::render` and GPU timings of each
+/// render pass, averaged over a rolling window.
+///
+/// GPU timings need `Features::TIMESTAMP_QUERY` and lag a frame or more
+/// behind, since they are read back without blocking.
+pub struct Profiler {
+    gpu: Option<GpuTimer>,
+    cpu: BTreeMap<&'static str, Rolling>,
+    gpu_passes: BTreeMap<&'static str, Rolling>,
+    start: Instant,
+    /// Events per frame, oldest first.
+    trace: VecDeque<Vec<TraceEvent>>,
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let gpu = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| {
+                let size = (MAX_PASSES * 2) as u64 * wgpu::QUERY_SIZE as u64;
+                GpuTimer {
+                    query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                        label: Some("profiler.query_set"),
+                        ty: wgpu::QueryType::Timestamp,
+                        count: MAX_PASSES * 2,
+                    }),
+                    readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("profiler.readback_buffer"),
+                        size,
+                        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                        mapped_at_creation: false,
+                    }),
+                    period: queue.get_timestamp_period(),
+                    passes: Vec::new(),
+                    open: false,
+                    pending: Vec::new(),
+                    requested: false,
+                    mapped: Arc::new(AtomicBool::new(false)),
+                    origin: None,
+                }
+            });
+
+        Self {
+            gpu,
+            cpu: BTreeMap::new(),
+            gpu_passes: BTreeMap::new(),
+            start: Instant::now(),
+            trace: VecDeque::with_capacity(TRACE_FRAMES),
+        }
+    }
+
+    /// Start a frame, collecting last frame's GPU timings if they are ready.
+    pub fn begin_frame(&mut self, device: &wgpu::Device) {
+        if self.trace.len() == TRACE_FRAMES {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(Vec::new());
+
+        let Some(gpu) = &mut self.gpu else {
+            return;
+        };
+        device.poll(wgpu::Maintain::Poll);
+        if !gpu.mapped.swap(false, Ordering::Acquire) {
+            return;
+        }
+
+        let slice = gpu.readback_buffer.slice(..);
+        let timestamps: Vec<u64> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        gpu.readback_buffer.unmap();
+        gpu.requested = false;
+
+        let origin = *gpu.origin.get_or_insert(timestamps[0]);
+        let events = self.trace.back_mut().unwrap();
+        for (name, pair) in gpu.pending.drain(..).zip(timestamps.chunks(2)) {
+            let nanos = |ticks: u64| ticks.saturating_sub(origin) as f64 * gpu.period as f64;
+            let duration = nanos(pair[1]) - nanos(pair[0]);
+            self.gpu_passes
+                .entry(name)
+                .or_default()
+                .push(Duration::from_nanos(duration as u64));
+            events.push(TraceEvent {
+                name,
+                gpu: true,
+                start: nanos(pair[0]) / 1000.0,
+                duration: duration / 1000.0,
+            });
+        }
+    }
+
+    /// Whether timestamps can be written this frame; not while the previous
+    /// readback is still in flight.
+    fn recording(&mut self) -> Option<&mut GpuTimer> {
+        self.gpu
+            .as_mut()
+            .filter(|gpu| gpu.pending.is_empty() && gpu.passes.len() < MAX_PASSES as usize)
+    }
+
+    pub fn begin_pass(&mut self, encoder: &mut wgpu::CommandEncoder, name: &'static str) {
+        if let Some(gpu) = self.recording() {
+            encoder.write_timestamp(&gpu.query_set, gpu.passes.len() as u32 * 2);
+            gpu.passes.push(name);
+            gpu.open = true;
+        }
+    }
+
+    pub fn end_pass(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(gpu) = self.gpu.as_mut().filter(|gpu| gpu.open) {
+            encoder.write_timestamp(&gpu.query_set, gpu.passes.len() as u32 * 2 - 1);
+            gpu.open = false;
+        }
+    }
+
+    /// Copy this frame's timestamps for reading back, before `encoder` is
+    /// submitted.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(gpu) = self.gpu.as_mut().filter(|gpu| gpu.pending.is_empty()) else {
+            return;
+        };
+        if gpu.passes.is_empty() {
+            return;
+        }
+        let count = gpu.passes.len() as u32 * 2;
+        encoder.resolve_query_set(&gpu.query_set, 0..count, &gpu.readback_buffer, 0);
+        gpu.pending = std::mem::take(&mut gpu.passes);
+    }
+
+    /// Start reading back the timestamps resolved this frame.
+    pub fn after_submit(&mut self) {
+        let Some(gpu) = &mut self.gpu else {
+            return;
+        };
+        if gpu.pending.is_empty() || gpu.requested {
+            return;
+        }
+        gpu.requested = true;
+        let mapped = gpu.mapped.clone();
+        gpu.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    mapped.store(true, Ordering::Release);
+                }
+            });
+    }
+
+    /// Record a CPU section that began at `start` and ends now.
+    pub fn record_cpu(&mut self, name: &'static str, start: Instant) {
+        let duration = start.elapsed();
+        self.cpu.entry(name).or_default().push(duration);
+        if let Some(events) = self.trace.back_mut() {
+            events.push(TraceEvent {
+                name,
+                gpu: false,
+                start: (start - self.start).as_secs_f64() * 1_000_000.0,
+                duration: duration.as_secs_f64() * 1_000_000.0,
+            });
+        }
+    }
+
+    /// One line per timed section with its average, for the HUD.
+    pub fn summary(&self) -> Vec<String> {
+        let line = |kind, name, rolling: &Rolling| {
+            format!(
+                "{} {}: {:.3} ms",
+                kind,
+                name,
+                rolling.mean().as_secs_f64() * 1000.0
+            )
+        };
+        let mut lines = self
+            .cpu
+            .iter()
+            .map(|(name, rolling)| line("cpu", name, rolling))
+            .collect::<Vec<_>>();
+        if self.gpu.is_none() {
+            lines.push("gpu: no timestamp queries".to_string());
+        }
+        lines.extend(
+            self.gpu_passes
+                .iter()
+                .map(|(name, rolling)| line("gpu", name, rolling)),
+        );
+        lines
+    }
+
+    /// The recent frames as Chrome trace JSON, for chrome://tracing or
+    /// Perfetto. CPU and GPU are separate threads with unrelated clocks.
+    pub fn chrome_trace(&self) -> String {
+        let events = self
+            .trace
+            .iter()
+            .flatten()
+            .map(|event| {
+                format!(
+                    r#"{{"name":"{}","cat":"{}","ph":"X","ts":{:.3},"dur":{:.3},"pid":0,"tid":{}}}"#,
+                    event.name,
+                    if event.gpu { "gpu" } else { "cpu" },
+                    event.start,
+                    event.duration,
+                    event.gpu as u8,
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(r#"{{"traceEvents":[{}]}}"#, events.join(","))
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::shader::Shader;
+
+/// A resource binding as declared in WGSL, merged across every shader that
+/// declares a global variable with the same name.
+#[derive(Debug)]
+struct Binding {
+    binding: u32,
+    ty: wgpu::BindingType,
+    visibility: wgpu::ShaderStages,
+    /// Byte size of buffer bindings.
+    size: Option<u64>,
+}
+
+/// Bind group and pipeline layouts generated from the WGSL source.
+///
+/// Bindings are looked up by their variable name (`camera`, `t_diffuse`, ..)
+/// rather than by group index, since the same resource may sit in a different
+/// group in each shader. Visibility is the union over all reflected shaders,
+/// so a layout built here is shared by every pipeline that uses the resource.
+pub struct Reflection {
+    bindings: HashMap<String, Binding>,
+    /// Variable names per group, in binding order, for each shader label.
+    groups: HashMap<String, BTreeMap<u32, Vec<String>>>,
+    /// `@workgroup_size` of `cs_main`, for each shader label that has one.
+    workgroup_sizes: HashMap<String, [u32; 3]>,
+}
+
+impl Reflection {
+    /// Every shader needs a label of its own, which is how its layouts are
+    /// found again.
+    pub fn new(shaders: &[&Shader]) -> Result<Self> {
+        let mut reflection = Self {
+            bindings: HashMap::new(),
+            groups: HashMap::new(),
+            workgroup_sizes: HashMap::new(),
+        };
+        for shader in shaders {
+            reflection.add(shader)?;
+        }
+        Ok(reflection)
+    }
+
+    fn add(&mut self, shader: &Shader) -> Result<()> {
+        let label = shader_label(shader)?;
+        ensure!(
+            !self.groups.contains_key(label),
+            "two shaders are labeled {}",
+            label
+        );
+        let source = shader.preprocess()?;
+        let module = naga::front::wgsl::parse_str(&source)
+            .map_err(|e| anyhow!("{}: {}", label, e.emit_to_string(&source)))?;
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|e| anyhow!("{}: {:?}", label, e))?;
+
+        let mut groups: BTreeMap<u32, BTreeMap<u32, String>> = BTreeMap::new();
+        for (handle, var) in module.global_variables.iter() {
+            let (Some(rb), Some(name)) = (&var.binding, &var.name) else {
+                continue;
+            };
+
+            let mut visibility = wgpu::ShaderStages::NONE;
+            for (index, entry_point) in module.entry_points.iter().enumerate() {
+                if !info.get_entry_point(index)[handle].is_empty() {
+                    visibility |= match entry_point.stage {
+                        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+                    };
+                }
+            }
+
+            let inner = &module.types[var.ty].inner;
+            let (ty, size) = binding_type(var.space, inner, &module.constants)
+                .map_err(|e| anyhow!("{}: `{}`: {}", label, name, e))?;
+
+            match self.bindings.get_mut(name) {
+                Some(existing) => {
+                    if existing.binding != rb.binding || existing.ty != ty {
+                        bail!(
+                            "{}: `{}` is declared differently than in an earlier shader",
+                            label,
+                            name
+                        );
+                    }
+                    existing.visibility |= visibility;
+                }
+                None => {
+                    self.bindings.insert(
+                        name.clone(),
+                        Binding {
+                            binding: rb.binding,
+                            ty,
+                            visibility,
+                            size,
+                        },
+                    );
+                }
+            }
+
+            groups
+                .entry(rb.group)
+                .or_default()
+                .insert(rb.binding, name.clone());
+        }
+
+        let groups = groups
+            .into_iter()
+            .map(|(group, names)| (group, names.into_values().collect()))
+            .collect();
+        self.groups.insert(label.to_string(), groups);
+
+        if let Some(entry_point) = module
+            .entry_points
+            .iter()
+            .find(|entry_point| entry_point.name == "cs_main")
+        {
+            self.workgroup_sizes
+                .insert(label.to_string(), entry_point.workgroup_size);
+        }
+
+        Ok(())
+    }
+
+    pub fn entries(&self, names: &[&str]) -> Result<Vec<wgpu::BindGroupLayoutEntry>> {
+        names
+            .iter()
+            .map(|name| {
+                let binding = self
+                    .bindings
+                    .get(*name)
+                    .ok_or_else(|| anyhow!("no shader declares a binding named `{}`", name))?;
+                Ok(wgpu::BindGroupLayoutEntry {
+                    binding: binding.binding,
+                    visibility: binding.visibility,
+                    ty: binding.ty,
+                    count: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Create the layout for a bind group holding the named bindings.
+    pub fn create_bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        names: &[&str],
+        label: Option<&str>,
+    ) -> Result<wgpu::BindGroupLayout> {
+        let entries = self.entries(names)?;
+        Ok(
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label,
+                entries: &entries,
+            }),
+        )
+    }
+
+    /// Create the pipeline layout for a reflected shader, one bind group
+    /// layout per group index it declares.
+    pub fn create_pipeline_layout(
+        &self,
+        device: &wgpu::Device,
+        shader: &Shader,
+        label: Option<&str>,
+    ) -> Result<wgpu::PipelineLayout> {
+        let shader_label = shader_label(shader)?;
+        let groups = self
+            .groups
+            .get(shader_label)
+            .ok_or_else(|| anyhow!("shader {} was not reflected", shader_label))?;
+        let count = groups.keys().next_back().map_or(0, |group| group + 1);
+
+        let layouts = (0..count)
+            .map(|group| {
+                let names = groups
+                    .get(&group)
+                    .map(|names| names.iter().map(String::as_str).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                self.create_bind_group_layout(device, &names, None)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label,
+                bind_group_layouts: &layouts.iter().collect::<Vec<_>>(),
+                push_constant_ranges: &[],
+            }),
+        )
+    }
+
+    /// The `@workgroup_size` of a reflected shader's `cs_main`.
+    pub fn workgroup_size(&self, shader: &Shader) -> Result<[u32; 3]> {
+        let shader_label = shader_label(shader)?;
+        self.workgroup_sizes
+            .get(shader_label)
+            .copied()
+            .ok_or_else(|| anyhow!("shader {} has no reflected cs_main", shader_label))
+    }
+
+    /// Compare Rust-side uniform sizes with the buffer sizes the shaders
+    /// expect, listing every mismatch.
+    pub fn check_sizes(&self, expected: &[(&str, usize)]) -> Result<()> {
+        let mismatches = expected
+            .iter()
+            .filter_map(|(name, size)| match self.bindings.get(*name) {
+                Some(Binding {
+                    size: Some(shader_size),
+                    ..
+                }) if *shader_size == *size as u64 => None,
+                Some(Binding {
+                    size: Some(shader_size),
+                    ..
+                }) => Some(format!(
+                    "`{}` is {} bytes in WGSL but {} bytes in Rust",
+                    name, shader_size, size
+                )),
+                Some(_) => Some(format!("`{}` is not a buffer binding", name)),
+                None => Some(format!("no shader declares a binding named `{}`", name)),
+            })
+            .collect::<Vec<_>>();
+
+        if !mismatches.is_empty() {
+            bail!("uniform layout mismatch:\n  {}", mismatches.join("\n  "));
+        }
+
+        Ok(())
+    }
+}
+
+/// What a shader's reflected layouts are kept under.
+fn shader_label<'a>(shader: &Shader<'a>) -> Result<&'a str> {
+    shader
+        .label()
+        .ok_or_else(|| anyhow!("only labeled shaders can be reflected"))
+}
+
+fn binding_type(
+    space: naga::AddressSpace,
+    inner: &naga::TypeInner,
+    constants: &naga::Arena<naga::Constant>,
+) -> Result<(wgpu::BindingType, Option<u64>)> {
+    let buffer = |ty| {
+        let size = inner.size(constants) as u64;
+        (
+            wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(size),
+            },
+            Some(size),
+        )
+    };
+
+    Ok(match (space, inner) {
+        (naga::AddressSpace::Uniform, _) => buffer(wgpu::BufferBindingType::Uniform),
+        (naga::AddressSpace::Storage { access }, _) => buffer(wgpu::BufferBindingType::Storage {
+            read_only: !access.contains(naga::StorageAccess::STORE),
+        }),
+        (naga::AddressSpace::Handle, naga::TypeInner::Sampler { comparison }) => (
+            wgpu::BindingType::Sampler(if *comparison {
+                wgpu::SamplerBindingType::Comparison
+            } else {
+                wgpu::SamplerBindingType::Filtering
+            }),
+            None,
+        ),
+        (
+            naga::AddressSpace::Handle,
+            naga::TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let view_dimension = match (dim, arrayed) {
+                (naga::ImageDimension::D1, false) => wgpu::TextureViewDimension::D1,
+                (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                (naga::ImageDimension::D3, false) => wgpu::TextureViewDimension::D3,
+                (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                _ => bail!("unsupported texture dimension {:?}", dim),
+            };
+            let ty = match class {
+                naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                    sample_type: match kind {
+                        naga::ScalarKind::Float => {
+                            wgpu::TextureSampleType::Float { filterable: true }
+                        }
+                        naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                        naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                        naga::ScalarKind::Bool => bail!("boolean textures are not supported"),
+                    },
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                naga::ImageClass::Storage { format, access } => wgpu::BindingType::StorageTexture {
+                    access: if access
+                        .contains(naga::StorageAccess::LOAD | naga::StorageAccess::STORE)
+                    {
+                        wgpu::StorageTextureAccess::ReadWrite
+                    } else if access.contains(naga::StorageAccess::STORE) {
+                        wgpu::StorageTextureAccess::WriteOnly
+                    } else {
+                        wgpu::StorageTextureAccess::ReadOnly
+                    },
+                    format: storage_format(*format)?,
+                    view_dimension,
+                },
+            };
+            (ty, None)
+        }
+        _ => bail!("unsupported resource type"),
+    })
+}
+
+fn storage_format(format: naga::StorageFormat) -> Result<wgpu::TextureFormat> {
+    Ok(match format {
+        naga::StorageFormat::R32Float => wgpu::TextureFormat::R32Float,
+        naga::StorageFormat::R32Uint => wgpu::TextureFormat::R32Uint,
+        naga::StorageFormat::Rg32Float => wgpu::TextureFormat::Rg32Float,
+        naga::StorageFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+        naga::StorageFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        naga::StorageFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+        _ => bail!("unsupported storage texture format {:?}", format),
+    })
+}
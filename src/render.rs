@@ -1,14 +1,7 @@
+use anyhow::Context;
+use wgpu::util::DeviceExt;
 
-
-
-
-
-
-
-
-
-
-
+use crate::shader::Shader;
 
 pub trait RenderPass {
     fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self;
@@ -17,6 +10,15 @@ pub trait RenderPass {
     fn update(&mut self, _queue: &wgpu::Queue) {}
 }
 
+/// Work recorded into its own compute pass each frame, such as simulation,
+/// culling or image processing. Unlike `RenderPass` there's no `new`, since
+/// compute passes usually need more than the device to be created.
+pub trait ComputePass {
+    fn resize(&mut self, _device: &wgpu::Device, _config: &wgpu::SurfaceConfiguration) {}
+    fn compute(&mut self, encoder: &mut wgpu::CommandEncoder);
+    fn update(&mut self, _queue: &wgpu::Queue) {}
+}
+
 /*pub trait RenderPass {
     fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self;
     fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration);
@@ -24,16 +26,45 @@ pub trait RenderPass {
     fn update(&mut self) {}
 }*/
 
+/// Fixed-function state that differs between pipeline variants.
+#[derive(Copy, Clone, Debug)]
+pub struct PipelineOptions {
+    pub topology: wgpu::PrimitiveTopology,
+    pub cull_mode: Option<wgpu::Face>,
+    /// Anything other than Fill requires the matching `Features::POLYGON_MODE_*`.
+    pub polygon_mode: wgpu::PolygonMode,
+    pub depth_compare: wgpu::CompareFunction,
+    pub depth_write_enabled: bool,
+    pub blend: Option<wgpu::BlendState>,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        Self {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            depth_compare: wgpu::CompareFunction::Less,
+            depth_write_enabled: true,
+            blend: Some(wgpu::BlendState::REPLACE),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn create_render_pipeline(
     device: &wgpu::Device,
     layout: &wgpu::PipelineLayout,
     color_format: wgpu::TextureFormat,
     depth_format: Option<wgpu::TextureFormat>,
     vertex_layouts: &[wgpu::VertexBufferLayout],
-    shader: wgpu::ShaderModuleDescriptor,
+    shader: &Shader,
+    options: &PipelineOptions,
     label: Option<&'static str>,
 ) -> wgpu::RenderPipeline {
-    let shader = device.create_shader_module(shader);
+    let shader = shader
+        .create_module(device)
+        .unwrap_or_else(|e| panic!("{:?}: {}", shader.label(), e));
 
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label,
@@ -48,20 +79,16 @@ pub fn create_render_pipeline(
             entry_point: "fs_main",
             targets: &[Some(wgpu::ColorTargetState {
                 format: color_format,
-                blend: Some(wgpu::BlendState {
-                    alpha: wgpu::BlendComponent::REPLACE,
-                    color: wgpu::BlendComponent::REPLACE,
-                }),
+                blend: options.blend,
                 write_mask: wgpu::ColorWrites::ALL,
             })],
         }),
         primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
+            topology: options.topology,
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-            polygon_mode: wgpu::PolygonMode::Fill,
+            cull_mode: options.cull_mode,
+            polygon_mode: options.polygon_mode,
             // Requires Features::DEPTH_CLIP_CONTROL
             unclipped_depth: false,
             // Requires Features::CONSERVATIVE_RASTERIZATION
@@ -69,8 +96,8 @@ pub fn create_render_pipeline(
         },
         depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
             format,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
+            depth_write_enabled: options.depth_write_enabled,
+            depth_compare: options.depth_compare,
             stencil: wgpu::StencilState::default(),
             bias: wgpu::DepthBiasState::default(),
         }),
@@ -82,3 +109,68 @@ pub fn create_render_pipeline(
         multiview: None,
     })
 }
+
+/// A compute pipeline running the shader's `cs_main`.
+pub fn create_compute_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &Shader,
+    label: Option<&'static str>,
+) -> anyhow::Result<wgpu::ComputePipeline> {
+    let module = shader
+        .create_module(device)
+        .with_context(|| format!("{:?}", shader.label()))?;
+
+    Ok(
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label,
+            layout: Some(layout),
+            module: &module,
+            entry_point: "cs_main",
+        }),
+    )
+}
+
+/// A buffer for `var<storage>`, initialized with `contents`. `usage` is added
+/// to `STORAGE`, e.g. `VERTEX` to draw from the results.
+pub fn create_storage_buffer<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    contents: &[T],
+    usage: wgpu::BufferUsages,
+    label: Option<&str>,
+) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label,
+        contents: bytemuck::cast_slice(contents),
+        usage: wgpu::BufferUsages::STORAGE | usage,
+    })
+}
+
+/// Workgroups to dispatch so that at least `invocations` threads run in each
+/// dimension, given the shader's `workgroup_size` (see
+/// `Reflection::workgroup_size`).
+pub fn dispatch_size(invocations: [u32; 3], workgroup_size: [u32; 3]) -> [u32; 3] {
+    [0, 1, 2].map(|i| invocations[i].div_ceil(workgroup_size[i]))
+}
+
+/// Whether vertex shaders can read storage buffers, which WebGL and some GLES
+/// devices can't.
+pub fn supports_vertex_storage(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+    adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
+        && device.limits().max_storage_buffers_per_shader_stage > 0
+}
+
+/// Whether compute shaders with storage buffers are available, which they
+/// aren't on WebGL.
+pub fn supports_compute(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+    let limits = device.limits();
+    adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        && limits.max_storage_buffers_per_shader_stage > 0
+        && limits.max_compute_invocations_per_workgroup > 0
+}
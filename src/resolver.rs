@@ -0,0 +1,124 @@
+use std::{fmt, io, path::PathBuf, sync::OnceLock};
+
+use crate::embedded;
+
+/// Names a directory to load assets from, as `--res dir` or `--res=dir`.
+pub const RES_FLAG: &str = "--res";
+/// Names a directory to load assets from, after `RES_FLAG`.
+pub const RES_ENV: &str = "LEARN_WGPU_RES";
+
+/// Somewhere files are looked for.
+#[derive(Clone, Debug)]
+pub enum Root {
+    Dir(PathBuf),
+    Embedded,
+}
+
+impl fmt::Display for Root {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Root::Dir(dir) => write!(f, "{}", dir.display()),
+            Root::Embedded => write!(f, "<embedded>"),
+        }
+    }
+}
+
+/// Finds files in the first of its roots that has them.
+#[derive(Clone, Debug)]
+pub struct Resolver {
+    roots: Vec<Root>,
+}
+
+impl Resolver {
+    /// `RES_FLAG` from `args`, then `RES_ENV`, then the `res` directory next
+    /// to the executable, where `build.rs` copies it, then what's embedded.
+    /// With the `embed-assets` feature, what's embedded comes first.
+    pub fn from_env(args: impl IntoIterator<Item = String>) -> Self {
+        let mut roots = Vec::new();
+        if embedded::ALL {
+            roots.push(Root::Embedded);
+        }
+        if let Some(dir) = flag(args) {
+            roots.push(Root::Dir(dir));
+        }
+        if let Some(dir) = std::env::var_os(RES_ENV) {
+            roots.push(Root::Dir(dir.into()));
+        }
+        match std::env::current_exe() {
+            Ok(exe) => roots.extend(exe.parent().map(|dir| Root::Dir(dir.join("res")))),
+            Err(e) => log::warn!("couldn't find the executable's directory: {}", e),
+        }
+        if !embedded::ALL {
+            roots.push(Root::Embedded);
+        }
+        Self { roots }
+    }
+
+    pub fn roots(&self) -> &[Root] {
+        &self.roots
+    }
+
+    /// The contents of `file_name`, or an error listing everywhere it was
+    /// looked for.
+    pub fn read(&self, file_name: &str) -> anyhow::Result<Vec<u8>> {
+        let mut tried = Vec::new();
+        for root in &self.roots {
+            match root {
+                Root::Dir(dir) => {
+                    let path = dir.join(file_name);
+                    match std::fs::read(&path) {
+                        Ok(data) => return Ok(data),
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                        Err(e) => anyhow::bail!("couldn't read {}: {}", path.display(), e),
+                    }
+                    tried.push(path.display().to_string());
+                }
+                Root::Embedded => {
+                    if let Some(data) = embedded::get(file_name) {
+                        return Ok(data.to_vec());
+                    }
+                    tried.push(format!("{} {}", root, file_name));
+                }
+            }
+        }
+        anyhow::bail!(
+            "couldn't find {}, tried:\n  {}",
+            file_name,
+            tried.join("\n  ")
+        )
+    }
+}
+
+/// The directory after `RES_FLAG`, skipping the program name.
+fn flag(args: impl IntoIterator<Item = String>) -> Option<PathBuf> {
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == RES_FLAG {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(dir) = arg.strip_prefix(RES_FLAG).and_then(|a| a.strip_prefix('=')) {
+            return Some(PathBuf::from(dir));
+        }
+    }
+    None
+}
+
+static RESOLVER: OnceLock<Resolver> = OnceLock::new();
+
+/// The resolver `resources` loads through, made from the process's
+/// arguments and environment the first time it's needed.
+pub fn get() -> &'static Resolver {
+    RESOLVER.get_or_init(|| {
+        let resolver = Resolver::from_env(std::env::args());
+        log::info!(
+            "loading assets from {}",
+            resolver
+                .roots()
+                .iter()
+                .map(Root::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        resolver
+    })
+}
@@ -1,9 +1,23 @@
-use std::io::{BufReader, Cursor};
+use std::{
+    io::{BufReader, Cursor},
+    sync::Arc,
+};
 
+use anyhow::Context;
 use cfg_if::cfg_if;
 use wgpu::util::DeviceExt;
 
-use crate::{model, texture};
+use cgmath::SquareMatrix;
+
+#[cfg(target_arch = "wasm32")]
+use crate::embedded;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::resolver;
+use crate::{
+    animation, model, optimize, skinned, tangents,
+    texture::{self, Texture},
+    vfs,
+};
 
 #[cfg(target_arch = "wasm32")]
 fn format_url(file_name: &str) -> reqwest::Url {
@@ -19,27 +33,60 @@ fn format_url(file_name: &str) -> reqwest::Url {
 }
 
 pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
-    cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            let url = format_url(file_name);
-            let txt = reqwest::get(url)
-                .await?
-                .text()
-                .await?;
-        } else {
-            let path = std::path::Path::new(env!("OUT_DIR"))
-                .join("res")
-                .join(file_name);
-            let txt = std::fs::read_to_string(path)?;
+    Ok(String::from_utf8(load_binary(file_name).await?)?)
+}
+
+/// Load `file_name` from the first of the archives and directories `vfs` has
+/// mounted over it that has it, or else from the assets.
+pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
+    let file_name = vfs::normalize(file_name);
+    let mounts = vfs::mounts(&file_name);
+    for (mount, path) in &mounts {
+        match mount {
+            vfs::Mount::Archive(archive) => {
+                if let Some(data) = archive.read(path) {
+                    return data;
+                }
+            }
+            vfs::Mount::Dir(dir) => {
+                if let Ok(data) = load_file(&format!("{}/{}", dir, path)).await {
+                    return Ok(data);
+                }
+            }
         }
     }
+    let data = load_file(&file_name).await;
+    if mounts.is_empty() {
+        return data;
+    }
+    data.with_context(|| {
+        let names = mounts.iter().map(|(mount, _)| mount.name());
+        let names = names.collect::<Vec<_>>().join(", ");
+        format!("{} isn't in {} either", file_name, names)
+    })
+}
 
-    Ok(txt)
+/// Mount the zip archive or the directory `file_name` at `prefix`, so that
+/// the files in it load as `prefix/...`.
+pub async fn mount(prefix: &str, file_name: &str) -> anyhow::Result<()> {
+    let mount = if file_name.ends_with(".zip") {
+        let archive = vfs::Archive::new(file_name, load_binary(file_name).await?)?;
+        vfs::Mount::Archive(Arc::new(archive))
+    } else {
+        vfs::Mount::Dir(vfs::normalize(file_name))
+    };
+    vfs::mount(prefix, mount);
+    Ok(())
 }
 
-pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
+async fn load_file(file_name: &str) -> anyhow::Result<Vec<u8>> {
     cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
+            if embedded::ALL {
+                if let Some(data) = embedded::get(file_name) {
+                    return Ok(data.to_vec());
+                }
+            }
             let url = format_url(file_name);
             let data = reqwest::get(url)
                 .await?
@@ -47,33 +94,69 @@ pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
                 .await?
                 .to_vec();
         } else {
-            let path = std::path::Path::new(env!("OUT_DIR"))
-                .join("res")
-                .join(file_name);
-            //println!("PATH:>{path:?}<");
-            let data = std::fs::read(path)?;
+            let data = resolver::get().read(file_name)?;
         }
     }
 
     Ok(data)
 }
 
-pub async fn load_texture(
-    file_name: &str,
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-) -> anyhow::Result<texture::Texture> {
-    //println!("load_texture:file_name:>{file_name}<");
+/// Load and decode an image, to upload as a texture on the main thread.
+pub async fn load_image(file_name: &str) -> anyhow::Result<image::DynamicImage> {
     let data = load_binary(file_name).await?;
-    texture::Texture::from_bytes(device, queue, &data, file_name, false)
+    Ok(image::load_from_memory(&data)?)
 }
 
-pub async fn load_model(
-    file_name: &str,
+/// Load a cubemap from six face images (+X, -X, +Y, -Y, +Z, -Z) or from a
+/// single equirectangular panorama.
+pub async fn load_cubemap(
+    file_names: &[&str],
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-    layout: &wgpu::BindGroupLayout,
-) -> anyhow::Result<model::Model> {
+) -> anyhow::Result<texture::Texture> {
+    let mut images = Vec::new();
+    for file_name in file_names {
+        images.push(image::load_from_memory(&load_binary(file_name).await?)?);
+    }
+
+    let faces = match images.as_slice() {
+        [panorama] => {
+            // Each face spans a quarter of the way around.
+            let size = panorama.width() / 4;
+            if size == 0 {
+                anyhow::bail!(
+                    "{} is too narrow for a cubemap, at {} pixels",
+                    file_names[0],
+                    panorama.width()
+                );
+            }
+            texture::equirectangular_to_cube_faces(panorama, size)
+        }
+        faces => faces.iter().map(|face| face.to_rgba8()).collect(),
+    };
+    texture::Texture::create_cubemap(device, queue, &faces, file_names.first().copied())
+}
+
+/// A material of an OBJ model, naming its textures.
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse_texture: String,
+    /// `None` for a flat normal map.
+    pub normal_texture: Option<String>,
+    pub alpha_mode: model::AlphaMode,
+    pub opacity: f32,
+}
+
+/// An OBJ model with its meshes ready to upload, loaded without touching the
+/// GPU so that it can be done on another thread.
+pub struct ObjData {
+    pub name: String,
+    /// Each with the index of its material.
+    pub meshes: Vec<(model::MeshData, usize)>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+pub async fn load_obj(file_name: &str) -> anyhow::Result<ObjData> {
     let obj_text = load_string(file_name).await?;
     //println!("obj_text:>{obj_text}");
     let obj_cursor = Cursor::new(obj_text);
@@ -86,30 +169,53 @@ pub async fn load_model(
             single_index: true,
             ..Default::default()
         },
-        |p| async move {
-            let mat_text = load_string(&p).await.unwrap();
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+        |p| {
+            let path = vfs::relative_to(file_name, &p);
+            async move {
+                match load_string(&path).await {
+                    Ok(mat_text) => tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text))),
+                    Err(e) => {
+                        log::warn!("couldn't load {}: {:#}", path, e);
+                        Err(tobj::LoadError::OpenFileFailed)
+                    }
+                }
+            }
         },
     )
     .await?;
 
-    let mut materials = Vec::new();
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(&m.diffuse_texture, device, queue).await?;
-        let normal_texture = load_texture(&m.normal_texture, device, queue).await?;
-        materials.push(model::Material::new(
-            device,
-            &m.name,
-            diffuse_texture,
-            normal_texture,
-            layout,
-        ));
-    }
+    let materials = obj_materials?
+        .into_iter()
+        .map(|m| ObjMaterial {
+            alpha_mode: if m.dissolve < 1.0 {
+                model::AlphaMode::Blend
+            } else if !m.dissolve_texture.is_empty() {
+                // Cut out by the diffuse texture's alpha, assumed to match `map_d`.
+                model::AlphaMode::Mask
+            } else {
+                model::AlphaMode::Opaque
+            },
+            opacity: m.dissolve,
+            // Relative to the OBJ file, which may be in an archive.
+            normal_texture: Some(m.normal_texture)
+                .filter(|t| !t.is_empty())
+                .map(|t| vfs::relative_to(file_name, &t)),
+            diffuse_texture: vfs::relative_to(file_name, &m.diffuse_texture),
+            name: m.name,
+        })
+        .collect();
 
     let meshes = models
         .into_iter()
         .map(|m| {
-            let mut vertices = (0..m.mesh.positions.len() / 3)
+            let num_vertices = m.mesh.positions.len() / 3;
+            if m.mesh.texcoords.len() < num_vertices * 2 {
+                anyhow::bail!("{}: {} has no texture coordinates", file_name, m.name);
+            }
+            if m.mesh.normals.len() < num_vertices * 3 {
+                anyhow::bail!("{}: {} has no normals", file_name, m.name);
+            }
+            let vertices = (0..num_vertices)
                 .map(|i| model::ModelVertex {
                     position: [
                         m.mesh.positions[i * 3],
@@ -122,99 +228,571 @@ pub async fn load_model(
                         m.mesh.normals[i * 3 + 1],
                         m.mesh.normals[i * 3 + 2],
                     ],
-                    tangent: [0.0; 3],
-                    bitangent: [0.0; 3],
+                    tangent: [0.0; 4],
                 })
                 .collect::<Vec<_>>();
+            let (data, _) = tangents::generate(&model::MeshData {
+                vertices,
+                indices: m.mesh.indices,
+            });
+            let (data, _) = optimize::optimize(&data, &format!("{} {}", file_name, m.name));
+            Ok((data, m.mesh.material_id.unwrap_or(0)))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(ObjData {
+        name: file_name.to_string(),
+        meshes,
+        materials,
+    })
+}
+
+impl ObjData {
+    /// Upload the model, with `texture` giving each texture by its path and
+    /// whether it's a normal map. A texture it has no answer for, as when it
+    /// failed to load, is replaced by a plain one.
+    pub fn create_model(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        texture: impl Fn(&str, bool) -> Option<Arc<Texture>>,
+    ) -> anyhow::Result<model::Model> {
+        let mut materials = Vec::new();
+        for m in &self.materials {
+            let diffuse_texture = match texture(&m.diffuse_texture, false) {
+                Some(texture) => texture,
+                None => solid_color_texture(device, queue, [0.5, 0.5, 0.5, 1.0], &m.name)?.into(),
+            };
+            let normal_texture = match m.normal_texture.as_deref().and_then(|t| texture(t, true)) {
+                Some(texture) => texture,
+                None => flat_normal_texture(device, queue, &m.name)?.into(),
+            };
+            materials.push(model::Material::new(
+                device,
+                &m.name,
+                diffuse_texture,
+                normal_texture,
+                m.alpha_mode,
+                m.opacity,
+                layout,
+            ));
+        }
+
+        let meshes = self
+            .meshes
+            .iter()
+            .map(|(data, material)| data.create_debug_mesh(device, &self.name, *material))
+            .collect();
+        Ok(model::Model { meshes, materials })
+    }
+}
 
-            let indices = &m.mesh.indices;
-            let mut triangles_included = vec![0; vertices.len()];
-
-            // Calculate tangents and bitangets. We're going to
-            // use the triangles, so we need to loop through the
-            // indices in chunks of 3
-            for c in indices.chunks(3) {
-                let v0 = vertices[c[0] as usize];
-                let v1 = vertices[c[1] as usize];
-                let v2 = vertices[c[2] as usize];
-
-                let pos0: cgmath::Vector3<_> = v0.position.into();
-                let pos1: cgmath::Vector3<_> = v1.position.into();
-                let pos2: cgmath::Vector3<_> = v2.position.into();
-
-                let uv0: cgmath::Vector2<_> = v0.tex_coords.into();
-                let uv1: cgmath::Vector2<_> = v1.tex_coords.into();
-                let uv2: cgmath::Vector2<_> = v2.tex_coords.into();
-
-                // Calculate the edges of the triangle
-                let delta_pos1 = pos1 - pos0;
-                let delta_pos2 = pos2 - pos0;
-
-                // This will give us a direction to calculate the
-                // tangent and bitangent
-                let delta_uv1 = uv1 - uv0;
-                let delta_uv2 = uv2 - uv0;
-
-                // Solving the following system of equations will
-                // give us the tangent and bitangent.
-                //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
-                //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
-                // Luckily, the place I found this equation provided
-                // the solution!
-                let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-                let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
-                // We flip the bitangent to enable right-handed normal
-                // maps with wgpu texture coordinate system
-                let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
-
-                // We'll use the same tangent/bitangent for each vertex in the triangle
-                vertices[c[0] as usize].tangent =
-                    (tangent + cgmath::Vector3::from(vertices[c[0] as usize].tangent)).into();
-                vertices[c[1] as usize].tangent =
-                    (tangent + cgmath::Vector3::from(vertices[c[1] as usize].tangent)).into();
-                vertices[c[2] as usize].tangent =
-                    (tangent + cgmath::Vector3::from(vertices[c[2] as usize].tangent)).into();
-                vertices[c[0] as usize].bitangent =
-                    (bitangent + cgmath::Vector3::from(vertices[c[0] as usize].bitangent)).into();
-                vertices[c[1] as usize].bitangent =
-                    (bitangent + cgmath::Vector3::from(vertices[c[1] as usize].bitangent)).into();
-                vertices[c[2] as usize].bitangent =
-                    (bitangent + cgmath::Vector3::from(vertices[c[2] as usize].bitangent)).into();
-
-                // Used to average the tangents/bitangents
-                triangles_included[c[0] as usize] += 1;
-                triangles_included[c[1] as usize] += 1;
-                triangles_included[c[2] as usize] += 1;
+/// Parse a glTF binary or JSON file and load its buffers.
+async fn open_gltf(file_name: &str) -> anyhow::Result<(gltf::Gltf, Vec<Vec<u8>>)> {
+    let gltf = gltf::Gltf::from_slice(&load_binary(file_name).await?)?;
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        buffers.push(match buffer.source() {
+            gltf::buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("{} has no binary chunk", file_name))?,
+            gltf::buffer::Source::Uri(uri) => load_gltf_uri(file_name, uri).await?,
+        });
+    }
+    Ok((gltf, buffers))
+}
+
+/// Load a file referenced by a glTF file, relative to it.
+async fn load_gltf_uri(file_name: &str, uri: &str) -> anyhow::Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        anyhow::bail!("{}: data URIs aren't supported", file_name);
+    }
+    load_binary(&vfs::relative_to(file_name, uri)).await
+}
+
+async fn load_gltf_texture(
+    gltf_texture: &gltf::Texture<'_>,
+    buffers: &[Vec<u8>],
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    is_normal_map: bool,
+) -> anyhow::Result<texture::Texture> {
+    let bytes = match gltf_texture.source().source() {
+        gltf::image::Source::View { view, .. } => {
+            let start = view.offset();
+            buffers[view.buffer().index()][start..start + view.length()].to_vec()
+        }
+        gltf::image::Source::Uri { uri, .. } => load_gltf_uri(file_name, uri).await?,
+    };
+    let label = gltf_texture.name().unwrap_or(file_name);
+    texture::Texture::from_bytes(device, queue, &bytes, label, is_normal_map)
+}
+
+/// Load every mesh of a glTF file, ignoring the node hierarchy.
+///
+/// With a `morph_layout`, meshes with morph targets get their
+/// `model::MorphTargets`, weighted separately for `num_instances` instances.
+pub async fn load_gltf_model(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    material_layout: &wgpu::BindGroupLayout,
+    morph_layout: Option<&wgpu::BindGroupLayout>,
+    num_instances: usize,
+) -> anyhow::Result<model::Model> {
+    let (gltf, buffers) = open_gltf(file_name).await?;
+
+    let mut materials = Vec::new();
+    for material in gltf.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let label = material.name().unwrap_or(file_name);
+        let (diffuse_texture, opacity) = match pbr.base_color_texture() {
+            Some(info) => (
+                load_gltf_texture(&info.texture(), &buffers, file_name, device, queue, false)
+                    .await?,
+                pbr.base_color_factor()[3],
+            ),
+            None => (
+                solid_color_texture(device, queue, pbr.base_color_factor(), label)?,
+                1.0,
+            ),
+        };
+        let normal_texture = match material.normal_texture() {
+            Some(info) => {
+                load_gltf_texture(&info.texture(), &buffers, file_name, device, queue, true).await?
+            }
+            None => flat_normal_texture(device, queue, label)?,
+        };
+        let alpha_mode = match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => model::AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => model::AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => model::AlphaMode::Blend,
+        };
+        materials.push(model::Material::new(
+            device,
+            label,
+            diffuse_texture,
+            normal_texture,
+            alpha_mode,
+            opacity,
+            material_layout,
+        ));
+    }
+    // For primitives without a material.
+    let default_material = materials.len();
+    materials.push(model::Material::new(
+        device,
+        file_name,
+        solid_color_texture(device, queue, [1.0; 4], file_name)?,
+        flat_normal_texture(device, queue, file_name)?,
+        model::AlphaMode::Opaque,
+        1.0,
+        material_layout,
+    ));
+
+    let mut meshes = Vec::new();
+    for mesh in gltf.meshes() {
+        let name = mesh.name().unwrap_or(file_name);
+        let target_names = morph_target_names(&mesh);
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions = reader
+                .read_positions()
+                .ok_or_else(|| anyhow::anyhow!("{}: primitive without positions", file_name))?
+                .collect::<Vec<_>>();
+            let mut normals = reader.read_normals();
+            let mut tex_coords = reader.read_tex_coords(0).map(|t| t.into_f32());
+            let mut given_tangents = reader.read_tangents();
+            let has_tangents = given_tangents.is_some();
+            let vertices = positions
+                .iter()
+                .map(|&position| model::ModelVertex {
+                    position,
+                    tex_coords: tex_coords
+                        .as_mut()
+                        .and_then(Iterator::next)
+                        .unwrap_or_default(),
+                    normal: normals
+                        .as_mut()
+                        .and_then(Iterator::next)
+                        .unwrap_or([0.0, 1.0, 0.0]),
+                    tangent: given_tangents
+                        .as_mut()
+                        .and_then(Iterator::next)
+                        .unwrap_or_default(),
+                })
+                .collect::<Vec<_>>();
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            let num_original = vertices.len();
+            let mut data = model::MeshData { vertices, indices };
+            // The vertex each vertex came from, through the tangents splitting
+            // and the optimizer reordering them, to take its morph deltas.
+            let mut origins = (0..num_original as u32).collect::<Vec<_>>();
+            if !has_tangents {
+                (data, origins) = tangents::generate(&data);
             }
+            let (data, optimized) = optimize::optimize(&data, name);
+            let origins = optimized
+                .iter()
+                .map(|&index| origins[index as usize])
+                .collect::<Vec<_>>();
 
-            // Average the tangents/bitangents
-            for (i, n) in triangles_included.into_iter().enumerate() {
-                let denom = 1.0 / n as f32;
-                let mut v = &mut vertices[i];
-                v.tangent = (cgmath::Vector3::from(v.tangent) * denom).into();
-                v.bitangent = (cgmath::Vector3::from(v.bitangent) * denom).into();
+            let mut names = Vec::new();
+            let mut deltas = Vec::new();
+            for (i, (positions, normals, tangents)) in reader.read_morph_targets().enumerate() {
+                let mut positions = positions.into_iter().flatten();
+                let mut normals = normals.into_iter().flatten();
+                let mut tangents = tangents.into_iter().flatten();
+                let target = (0..num_original)
+                    .map(|_| {
+                        let [x, y, z] = positions.next().unwrap_or_default();
+                        let [nx, ny, nz] = normals.next().unwrap_or_default();
+                        let [tx, ty, tz] = tangents.next().unwrap_or_default();
+                        model::MorphDelta {
+                            position: [x, y, z, 0.0],
+                            normal: [nx, ny, nz, 0.0],
+                            tangent: [tx, ty, tz, 0.0],
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                deltas.extend(origins.iter().map(|&origin| target[origin as usize]));
+                names.push(
+                    target_names
+                        .get(i)
+                        .cloned()
+                        .unwrap_or_else(|| format!("target {}", i)),
+                );
             }
+            let morph_targets = match morph_layout {
+                Some(layout) if !names.is_empty() => Some(model::MorphTargets::new(
+                    device,
+                    name,
+                    names,
+                    &deltas,
+                    num_instances,
+                    layout,
+                )),
+                _ => None,
+            };
 
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
+            let mut mesh = data.create_mesh(
+                device,
+                name,
+                primitive.material().index().unwrap_or(default_material),
+            );
+            mesh.morph_targets = morph_targets;
+            meshes.push(mesh);
+        }
+    }
+
+    Ok(model::Model { meshes, materials })
+}
+
+/// Names of a mesh's morph targets from the `targetNames` extra, as
+/// exported by Blender and others.
+fn morph_target_names(mesh: &gltf::Mesh) -> Vec<String> {
+    mesh.extras()
+        .as_ref()
+        .and_then(|extras| serde_json::from_str::<serde_json::Value>(extras.get()).ok())
+        .and_then(|extras| {
+            extras.get("targetNames")?.as_array().map(|names| {
+                names
+                    .iter()
+                    .map(|name| name.as_str().unwrap_or_default().to_string())
+                    .collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// Load the first skin of a glTF binary or JSON file, with every mesh it
+/// deforms and the animations of its joints.
+///
+/// Only the base color of each material is used. Buffers and images must be
+/// embedded or next to the file; `data:` URIs aren't supported.
+pub async fn load_gltf(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture_layout: &wgpu::BindGroupLayout,
+    joints_layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<skinned::SkinnedModel> {
+    let (gltf, buffers) = open_gltf(file_name).await?;
+
+    let mut materials = Vec::new();
+    for material in gltf.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let label = material.name().unwrap_or(file_name);
+        let texture = match pbr.base_color_texture() {
+            Some(info) => {
+                load_gltf_texture(&info.texture(), &buffers, file_name, device, queue, false)
+                    .await?
+            }
+            None => solid_color_texture(device, queue, pbr.base_color_factor(), label)?,
+        };
+        materials.push(texture.create_bind_group(device, texture_layout, Some(label)));
+    }
+    // For primitives without a material.
+    let default_material = materials.len();
+    let texture = solid_color_texture(device, queue, [1.0; 4], file_name)?;
+    materials.push(texture.create_bind_group(device, texture_layout, Some(file_name)));
+
+    let skin = gltf
+        .skins()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("{} has no skin", file_name))?;
+
+    let mut parents = vec![None; gltf.nodes().len()];
+    for node in gltf.nodes() {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+    // Node index to index in `skin.joints()`.
+    let mut joint_of_node = vec![None; gltf.nodes().len()];
+    for (i, node) in skin.joints().enumerate() {
+        joint_of_node[node.index()] = Some(i);
+    }
 
-            model::Mesh {
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
+    let inverse_binds = skin
+        .reader(|buffer| Some(&buffers[buffer.index()]))
+        .read_inverse_bind_matrices()
+        .map(|matrices| matrices.map(cgmath::Matrix4::from).collect::<Vec<_>>());
+    let nodes = gltf.nodes().collect::<Vec<_>>();
+    let joints = skin
+        .joints()
+        .enumerate()
+        .map(|(i, node)| {
+            // Walk up to the nearest joint, collecting the nodes in between.
+            let mut parent = None;
+            let mut base = cgmath::Matrix4::identity();
+            let mut ancestor = parents[node.index()];
+            while let Some(index) = ancestor {
+                if let Some(joint) = joint_of_node[index] {
+                    parent = Some(joint);
+                    break;
+                }
+                base = cgmath::Matrix4::from(nodes[index].transform().matrix()) * base;
+                ancestor = parents[index];
+            }
+            let (translation, rotation, scale) = node.transform().decomposed();
+            animation::Joint {
+                parent,
+                rest: animation::Transform {
+                    translation: translation.into(),
+                    rotation: quaternion(rotation),
+                    scale: scale.into(),
+                },
+                inverse_bind: inverse_binds
+                    .as_ref()
+                    .and_then(|matrices| matrices.get(i).copied())
+                    .unwrap_or_else(cgmath::Matrix4::identity),
+                // Nodes between joints aren't animated, so fold them into the
+                // root only.
+                base: if parent.is_none() {
+                    base
+                } else {
+                    cgmath::Matrix4::identity()
+                },
             }
         })
-        .collect::<Vec<_>>();
+        .collect();
+    let (skeleton, new_index) = animation::Skeleton::new(joints);
 
-    Ok(model::Model { meshes, materials })
+    let mut meshes = Vec::new();
+    for node in gltf.nodes() {
+        let (Some(mesh), Some(node_skin)) = (node.mesh(), node.skin()) else {
+            continue;
+        };
+        if node_skin.index() != skin.index() {
+            continue;
+        }
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions = reader
+                .read_positions()
+                .ok_or_else(|| anyhow::anyhow!("{}: primitive without positions", file_name))?
+                .collect::<Vec<_>>();
+            let mut normals = reader.read_normals();
+            let mut tex_coords = reader.read_tex_coords(0).map(|t| t.into_f32());
+            let mut vertex_joints = reader.read_joints(0).map(|j| j.into_u16());
+            let mut weights = reader.read_weights(0).map(|w| w.into_f32());
+
+            let vertices = positions
+                .into_iter()
+                .map(|position| skinned::SkinnedVertex {
+                    position,
+                    tex_coords: tex_coords
+                        .as_mut()
+                        .and_then(Iterator::next)
+                        .unwrap_or_default(),
+                    normal: normals
+                        .as_mut()
+                        .and_then(Iterator::next)
+                        .unwrap_or([0.0, 1.0, 0.0]),
+                    joints: vertex_joints
+                        .as_mut()
+                        .and_then(Iterator::next)
+                        .unwrap_or_default()
+                        .map(|joint| new_index.get(joint as usize).copied().unwrap_or(0) as u32),
+                    weights: weights
+                        .as_mut()
+                        .and_then(Iterator::next)
+                        .unwrap_or([1.0, 0.0, 0.0, 0.0]),
+                })
+                .collect::<Vec<_>>();
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+
+            let name = mesh.name().unwrap_or(file_name);
+            meshes.push(skinned::SkinnedMesh {
+                vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{:?} Vertex Buffer", name)),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                }),
+                index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{:?} Index Buffer", name)),
+                    contents: bytemuck::cast_slice(&indices),
+                    usage: wgpu::BufferUsages::INDEX,
+                }),
+                num_elements: indices.len() as u32,
+                material: primitive.material().index().unwrap_or(default_material),
+            });
+        }
+    }
+
+    let mut clips = Vec::new();
+    for gltf_animation in gltf.animations() {
+        let mut channels = Vec::new();
+        let mut duration = 0.0f32;
+        for channel in gltf_animation.channels() {
+            let Some(joint) = joint_of_node[channel.target().node().index()] else {
+                continue;
+            };
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let Some(times) = reader.read_inputs().map(|t| t.collect::<Vec<_>>()) else {
+                continue;
+            };
+            let Some(outputs) = reader.read_outputs() else {
+                continue;
+            };
+            let interpolation = channel.sampler().interpolation();
+            let keyframes = match outputs {
+                gltf::animation::util::ReadOutputs::Translations(t) => {
+                    animation::Keyframes::Translation(
+                        keyframe_values(t.collect(), interpolation)
+                            .into_iter()
+                            .map(Into::into)
+                            .collect(),
+                    )
+                }
+                gltf::animation::util::ReadOutputs::Rotations(r) => animation::Keyframes::Rotation(
+                    keyframe_values(r.into_f32().collect(), interpolation)
+                        .into_iter()
+                        .map(quaternion)
+                        .collect(),
+                ),
+                gltf::animation::util::ReadOutputs::Scales(s) => animation::Keyframes::Scale(
+                    keyframe_values(s.collect(), interpolation)
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                ),
+                gltf::animation::util::ReadOutputs::MorphTargetWeights(_) => continue,
+            };
+            duration = duration.max(times.last().copied().unwrap_or(0.0));
+            channels.push(animation::Channel {
+                joint: new_index[joint],
+                times,
+                keyframes,
+                interpolation: match interpolation {
+                    gltf::animation::Interpolation::Step => animation::Interpolation::Step,
+                    _ => animation::Interpolation::Linear,
+                },
+            });
+        }
+        clips.push(animation::AnimationClip {
+            name: gltf_animation
+                .name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("animation {}", gltf_animation.index())),
+            duration,
+            channels,
+        });
+    }
+
+    Ok(skinned::SkinnedModel::new(
+        device,
+        meshes,
+        materials,
+        skeleton,
+        clips,
+        joints_layout,
+    ))
+}
+
+/// Cubic splines store in-tangent, value, out-tangent per key; keep the
+/// values, to be interpolated linearly.
+fn keyframe_values<T: Copy>(
+    values: Vec<T>,
+    interpolation: gltf::animation::Interpolation,
+) -> Vec<T> {
+    match interpolation {
+        gltf::animation::Interpolation::CubicSpline => values
+            .chunks(3)
+            .filter_map(|key| key.get(1).copied())
+            .collect(),
+        _ => values,
+    }
+}
+
+/// glTF stores quaternions as x, y, z, w.
+fn quaternion([x, y, z, w]: [f32; 4]) -> cgmath::Quaternion<f32> {
+    cgmath::Quaternion::new(w, x, y, z)
+}
+
+/// A 1x1 normal map pointing straight out, for materials without one.
+pub fn flat_normal_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: &str,
+) -> anyhow::Result<texture::Texture> {
+    let pixel = image::Rgba([128, 128, 255, 255]);
+    let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, pixel));
+    texture::Texture::from_image(device, queue, &img, Some(label), true)
+}
+
+/// A 1x1 texture of a linear RGBA color, for materials without a texture.
+pub fn solid_color_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    color: [f32; 4],
+    label: &str,
+) -> anyhow::Result<texture::Texture> {
+    let to_srgb = |c: f32| {
+        let c = c.clamp(0.0, 1.0);
+        let srgb = if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        (srgb * 255.0).round() as u8
+    };
+    let pixel = image::Rgba([
+        to_srgb(color[0]),
+        to_srgb(color[1]),
+        to_srgb(color[2]),
+        (color[3].clamp(0.0, 1.0) * 255.0).round() as u8,
+    ]);
+    let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, pixel));
+    texture::Texture::from_image(device, queue, &img, Some(label), false)
 }
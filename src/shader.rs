@@ -0,0 +1,281 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+
+/// Snippets available to `#include`, embedded so they also work on the web.
+const INCLUDES: &[(&str, &str)] = &[
+    ("camera.wgsl", include_str!("include/camera.wgsl")),
+    ("emitter.wgsl", include_str!("include/emitter.wgsl")),
+    ("light.wgsl", include_str!("include/light.wgsl")),
+    ("ibl.wgsl", include_str!("include/ibl.wgsl")),
+    ("material.wgsl", include_str!("include/material.wgsl")),
+    ("morph.wgsl", include_str!("include/morph.wgsl")),
+    ("rotation.wgsl", include_str!("include/rotation.wgsl")),
+    (
+        "vertex_input.wgsl",
+        include_str!("include/vertex_input.wgsl"),
+    ),
+    (
+        "instance_input.wgsl",
+        include_str!("include/instance_input.wgsl"),
+    ),
+];
+
+/// WGSL source plus the defines used to preprocess it.
+///
+/// Supports `#include "file"`, `#define NAME [value]`, `#ifdef NAME`,
+/// `#ifndef NAME`, `#else` and `#endif`. Defines with a value are substituted
+/// wherever `NAME` appears as a whole identifier, and each include is pasted
+/// at most once per module.
+pub struct Shader<'a> {
+    label: Option<&'a str>,
+    source: &'a str,
+    defines: HashMap<String, String>,
+}
+
+impl<'a> Shader<'a> {
+    pub fn new(label: Option<&'a str>, source: &'a str) -> Self {
+        Self {
+            label,
+            source,
+            defines: HashMap::new(),
+        }
+    }
+
+    /// Set a flag for `#ifdef`.
+    pub fn define(self, name: &str) -> Self {
+        self.define_value(name, "")
+    }
+
+    /// Set a define that is substituted into the source.
+    pub fn define_value(mut self, name: &str, value: impl ToString) -> Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn label(&self) -> Option<&'a str> {
+        self.label
+    }
+
+    /// Expand includes and conditionals into plain WGSL.
+    pub fn preprocess(&self) -> Result<String> {
+        let mut defines = self.defines.clone();
+        let mut included = HashSet::new();
+        let mut out = String::new();
+        expand(
+            self.label.unwrap_or("<shader>"),
+            self.source,
+            &mut defines,
+            &mut included,
+            &mut out,
+        )?;
+        Ok(out)
+    }
+
+    pub fn create_module(&self, device: &wgpu::Device) -> Result<wgpu::ShaderModule> {
+        let source = self.preprocess()?;
+        Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: self.label,
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        }))
+    }
+}
+
+fn expand(
+    file: &str,
+    source: &str,
+    defines: &mut HashMap<String, String>,
+    included: &mut HashSet<String>,
+    out: &mut String,
+) -> Result<()> {
+    // One entry per open `#if*`: whether its current branch is emitted.
+    let mut stack: Vec<bool> = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let at = || format!("{}:{}", file, number + 1);
+        let active = stack.iter().all(|&emit| emit);
+        let trimmed = line.trim_start();
+
+        let Some(directive) = trimmed.strip_prefix('#') else {
+            if active {
+                out.push_str(&substitute(line, defines));
+                out.push('\n');
+            }
+            continue;
+        };
+
+        let mut words = directive.split_whitespace();
+        let keyword = words.next().unwrap_or("");
+        match keyword {
+            "ifdef" | "ifndef" => {
+                let name = words
+                    .next()
+                    .ok_or_else(|| anyhow!("{}: #{} without a name", at(), keyword))?;
+                let is_defined = defines.contains_key(name);
+                stack.push(is_defined == (keyword == "ifdef"));
+            }
+            "else" => {
+                let emit = stack
+                    .last_mut()
+                    .ok_or_else(|| anyhow!("{}: #else without #ifdef", at()))?;
+                *emit = !*emit;
+            }
+            "endif" => {
+                stack
+                    .pop()
+                    .ok_or_else(|| anyhow!("{}: #endif without #ifdef", at()))?;
+            }
+            _ if !active => {}
+            "define" => {
+                let name = words
+                    .next()
+                    .ok_or_else(|| anyhow!("{}: #define without a name", at()))?;
+                let value = words.collect::<Vec<_>>().join(" ");
+                defines.insert(name.to_string(), value);
+            }
+            "include" => {
+                let name = directive["include".len()..].trim().trim_matches('"');
+                let (_, snippet) = INCLUDES
+                    .iter()
+                    .find(|(include, _)| *include == name)
+                    .ok_or_else(|| anyhow!("{}: unknown include \"{}\"", at(), name))?;
+                if included.insert(name.to_string()) {
+                    expand(name, snippet, defines, included, out)?;
+                }
+            }
+            _ => bail!("{}: unknown directive #{}", at(), keyword),
+        }
+    }
+
+    if !stack.is_empty() {
+        bail!("{}: missing #endif", file);
+    }
+
+    Ok(())
+}
+
+/// Replace identifiers that name a valued define.
+fn substitute(line: &str, defines: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut ident = String::new();
+    let flush = |ident: &mut String, out: &mut String| {
+        match defines.get(ident.as_str()) {
+            Some(value) if !value.is_empty() => out.push_str(value),
+            _ => out.push_str(ident),
+        }
+        ident.clear();
+    };
+
+    for c in line.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            ident.push(c);
+        } else {
+            flush(&mut ident, &mut out);
+            out.push(c);
+        }
+    }
+    flush(&mut ident, &mut out);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(shader: Shader) -> String {
+        shader.preprocess().unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        format!(
+            "{:#}",
+            Shader::new(Some("test"), source).preprocess().unwrap_err()
+        )
+    }
+
+    #[test]
+    fn include() {
+        let source = "#include \"rotation.wgsl\"\n#include \"rotation.wgsl\"\nfn main() {}";
+        let out = preprocess(Shader::new(None, source).define_value("ROTATION_GROUP", 2));
+        assert_eq!(out.matches("struct RotationUniform").count(), 1);
+        assert!(out.contains("@group(2) @binding(0)"));
+        assert!(out.ends_with("fn main() {}\n"));
+    }
+
+    #[test]
+    fn conditionals() {
+        let source = "\
+#ifdef A
+a
+#ifndef B
+not b
+#else
+b
+#endif
+#else
+not a
+#ifdef B
+a and b
+#endif
+#endif
+end";
+        let out = |shader: Shader| {
+            preprocess(shader)
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(out(Shader::new(None, source)), ["not a", "end"]);
+        assert_eq!(
+            out(Shader::new(None, source).define("A")),
+            ["a", "not b", "end"]
+        );
+        assert_eq!(
+            out(Shader::new(None, source).define("A").define("B")),
+            ["a", "b", "end"]
+        );
+        assert_eq!(
+            out(Shader::new(None, source).define("B")),
+            ["not a", "a and b", "end"]
+        );
+    }
+
+    #[test]
+    fn substitution() {
+        let source = "\
+#define SIZE 4
+#define FLAG
+var<private> a: array<f32, SIZE>;
+let b = COUNT * SIZE_2 + SIZE;
+let c = FLAG;
+#ifdef FLAG
+let d = COUNT;
+#endif";
+        let out = preprocess(Shader::new(None, source).define_value("COUNT", 3));
+        assert_eq!(
+            out,
+            "var<private> a: array<f32, 4>;\nlet b = 3 * SIZE_2 + 4;\nlet c = FLAG;\nlet d = 3;\n",
+        );
+    }
+
+    #[test]
+    fn defines_in_skipped_branches() {
+        let source = "#ifdef A\n#define B 1\n#endif\n#ifdef B\nb\n#endif\nB";
+        assert_eq!(preprocess(Shader::new(None, source)), "B\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("#ifdef A\na"), "test: missing #endif");
+        assert_eq!(error("#ifndef A\n#ifdef B\n#endif"), "test: missing #endif");
+        assert_eq!(error("a\n#endif"), "test:2: #endif without #ifdef");
+        assert_eq!(error("#else"), "test:1: #else without #ifdef");
+        assert_eq!(error("#ifdef"), "test:1: #ifdef without a name");
+        assert_eq!(
+            error("a\n#include \"missing.wgsl\""),
+            "test:2: unknown include \"missing.wgsl\""
+        );
+        assert_eq!(error("#pragma once"), "test:1: unknown directive #pragma");
+    }
+}
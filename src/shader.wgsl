@@ -1,38 +1,8 @@
-struct CameraUniform {
-    view_pos: vec4<f32>,
-    view_proj: mat4x4<f32>,
-};
-@group(1) @binding(0)
-var<uniform> camera: CameraUniform;
-
-struct RotationUniform {
-    view_proj: mat4x4<f32>,
-};
-@group(2) @binding(0)
-var<uniform> rotation: RotationUniform;
-
-struct Light {
-    position: vec3<f32>,
-    color: vec3<f32>,
-}
-@group(3) @binding(0)
-var<uniform> light: Light;
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
-    @location(2) normal: vec3<f32>,
-}
-
-struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>,
-    @location(9) normal_matrix_0: vec3<f32>,
-    @location(10) normal_matrix_1: vec3<f32>,
-    @location(11) normal_matrix_2: vec3<f32>,
-};
+#include "camera.wgsl"
+#include "rotation.wgsl"
+#include "light.wgsl"
+#include "vertex_input.wgsl"
+#include "instance_input.wgsl"
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
@@ -100,6 +70,10 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+// For `AlphaMode::Mask`, since these textures have no material to hold a
+// cutoff.
+let ALPHA_CUTOFF: f32 = 0.5;
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     //return textureSample(t_diffuse, s_diffuse, in.tex_coords);
@@ -129,5 +103,10 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     //let result = diffuse_color * object_color.xyz;
     //let result = specular_color * object_color.xyz;
 
+#ifdef ALPHA_MASK
+    if (object_color.a < ALPHA_CUTOFF) {
+        discard;
+    }
+#endif
     return vec4<f32>(result, object_color.a);
 }
@@ -1,44 +1,59 @@
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
-}
-
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
 }
 
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.tex_coords = model.tex_coords;
-    out.clip_position = vec4<f32>(model.position, 1.0);
-    return out;
-}
-
-// Fragment shader
+struct OverlayUniform {
+    // Left, top, width and height, as fractions of the screen.
+    rect: vec4<f32>,
+    znear: f32,
+    zfar: f32,
+    // The linear depths at either end of the colormap.
+    min_depth: f32,
+    max_depth: f32,
+};
 
+// Declared as a float texture, since GL only samples depth textures by
+// comparing against them.
 @group(0) @binding(0)
-var t_shadow: texture_depth_2d;
+var t_depth: texture_2d<f32>;
 @group(0) @binding(1)
-var s_shadow: sampler_comparison;
+var s_depth: sampler;
+@group(0) @binding(2)
+var<uniform> overlay: OverlayUniform;
+@group(0) @binding(3)
+var t_colormap: texture_2d<f32>;
+@group(0) @binding(4)
+var s_colormap: sampler;
 
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Two triangles covering `overlay.rect`.
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+    );
+    let corner = corners[index];
+    let screen = overlay.rect.xy + corner * overlay.rect.zw;
 
-struct GradientUniform {
-    c: vec4<f32>,
-};
-@group(1) @binding(0)
-var<uniform> g: GradientUniform;
+    var out: VertexOutput;
+    out.tex_coords = corner;
+    out.clip_position = vec4<f32>(screen.x * 2.0 - 1.0, 1.0 - screen.y * 2.0, 0.0, 1.0);
+    return out;
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let near = 0.1;
-    let far = 100.0;
-    let depth = textureSampleCompare(t_shadow, s_shadow, in.tex_coords, in.clip_position.w);
-    //let r = (2.0 * near) / (far + near - depth * (far - near));
-    let r = depth;
-    //return vec4<f32>(vec3<f32>(r * 0.5 + 0.3, r * 0.5 + 0.3, r), 1.0);
-    return vec4<f32>(g.c[0] * r, g.c[1] * r, g.c[2] * r, 1.0);
+    let depth = textureSample(t_depth, s_depth, in.tex_coords).r;
+
+    // Undo the projection, which puts `znear` at 0 and `zfar` at 1.
+    let near = overlay.znear;
+    let far = overlay.zfar;
+    let distance = near * far / (far - depth * (far - near));
+    let t = clamp((distance - overlay.min_depth) / (overlay.max_depth - overlay.min_depth), 0.0, 1.0);
+    return textureSample(t_colormap, s_colormap, vec2<f32>(t, 0.5));
 }
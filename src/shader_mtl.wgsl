@@ -1,40 +1,15 @@
-struct CameraUniform {
-    view_pos: vec4<f32>,
-    view_proj: mat4x4<f32>,
-};
-@group(1) @binding(0)
-var<uniform> camera: CameraUniform;
-
-struct RotationUniform {
-    view_proj: mat4x4<f32>,
-};
-@group(2) @binding(0)
-var<uniform> rotation: RotationUniform;
-
-struct Light {
-    position: vec3<f32>,
-    color: vec3<f32>,
-}
-@group(3) @binding(0)
-var<uniform> light: Light;
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
-    @location(2) normal: vec3<f32>,
-    @location(3) tangent: vec3<f32>,
-    @location(4) bitangent: vec3<f32>,
-}
-
-struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>,
-    @location(9) normal_matrix_0: vec3<f32>,
-    @location(10) normal_matrix_1: vec3<f32>,
-    @location(11) normal_matrix_2: vec3<f32>,
-};
+#include "camera.wgsl"
+#ifndef MORPH_TARGETS
+#include "rotation.wgsl"
+#endif
+#include "light.wgsl"
+#include "ibl.wgsl"
+#include "material.wgsl"
+#include "vertex_input.wgsl"
+#include "instance_input.wgsl"
+#ifdef MORPH_TARGETS
+#include "morph.wgsl"
+#endif
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
@@ -42,24 +17,45 @@ struct VertexOutput {
     @location(1) tangent_position: vec3<f32>,
     @location(2) tangent_light_position: vec3<f32>,
     @location(3) tangent_view_position: vec3<f32>,
+    @location(4) world_position: vec3<f32>,
+    @location(5) world_normal: vec3<f32>,
+    @location(6) world_tangent: vec3<f32>,
+    @location(7) world_bitangent: vec3<f32>,
 }
 
 @vertex
 fn vs_main(
-    model: VertexInput,
+    vertex: VertexInput,
     instance: InstanceInput,
+#ifdef MORPH_TARGETS
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+#endif
 ) -> VertexOutput {
+#ifdef MORPH_TARGETS
+    // The morph targets take the rotation's group.
+    let model = apply_morph_targets(vertex, vertex_index, instance_index);
+    let model_rotation = mat4x4<f32>(
+        vec4<f32>(1.0, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, 1.0, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    );
+#else
+    let model = vertex;
+    let model_rotation = rotation.view_proj;
+#endif
     let model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
         instance.model_matrix_1,
         instance.model_matrix_2,
         instance.model_matrix_3,
-    ) * rotation.view_proj;
+    ) * model_rotation;
 
     let rotation_3x3 = mat3x3<f32>(
-        rotation.view_proj[0].xyz,
-        rotation.view_proj[1].xyz,
-        rotation.view_proj[2].xyz,
+        model_rotation[0].xyz,
+        model_rotation[1].xyz,
+        model_rotation[2].xyz,
     );
 
     let normal_matrix = mat3x3<f32>(
@@ -70,8 +66,8 @@ fn vs_main(
 
     // Construct the tangent matrix.
     let world_normal = normalize(normal_matrix * model.normal);
-    let world_tangent = normalize(normal_matrix * model.tangent);
-    let world_bitangent = normalize(normal_matrix * model.bitangent);
+    let world_tangent = normalize(normal_matrix * model.tangent.xyz);
+    let world_bitangent = cross(world_normal, world_tangent) * model.tangent.w;
     let tangent_matrix = transpose(mat3x3<f32>(
         world_tangent,
         world_bitangent,
@@ -87,28 +83,47 @@ fn vs_main(
     out.tangent_position = tangent_matrix * world_position.xyz;
     out.tangent_view_position = tangent_matrix * camera.view_pos.xyz;
     out.tangent_light_position = tangent_matrix * light.position;
+    out.world_position = world_position.xyz;
+    out.world_normal = world_normal;
+    out.world_tangent = world_tangent;
+    out.world_bitangent = world_bitangent;
     return out;
 }
 
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0) @binding(1)
-var s_diffuse: sampler;
-@group(0) @binding(2)
-var t_normal: texture_2d<f32>;
-@group(0) @binding(3)
-var s_normal: sampler;
+// `MAX_LOD`, the highest mip of `t_prefiltered`, is defined with
+// `ibl::Ibl::PREFILTERED_MIPS` wherever this shader is built.
+let ROUGHNESS: f32 = 0.5;
+
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let object_color: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
     let object_normal: vec4<f32> = textureSample(t_normal, s_normal, in.tex_coords);
 
-    // We don't need (or want) much ambient light, so 0.1 is fine
-    let ambient_strength = 0.1;
-    let ambient_color = light.color * ambient_strength;
-
     let tangent_normal = object_normal.xyz * 2.0 - 1.0;
+
+    // Ambient light from the environment, using the split-sum approximation.
+    let normal = normalize(mat3x3<f32>(
+        normalize(in.world_tangent),
+        normalize(in.world_bitangent),
+        normalize(in.world_normal),
+    ) * tangent_normal);
+    let world_view_dir = normalize(camera.view_pos.xyz - in.world_position);
+    let n_dot_v = max(dot(normal, world_view_dir), 0.0);
+    let f = fresnel_schlick_roughness(n_dot_v, vec3<f32>(0.04), ROUGHNESS);
+    let irradiance = textureSample(t_irradiance, s_ibl, normal).rgb;
+    let prefiltered = textureSampleLevel(
+        t_prefiltered,
+        s_ibl,
+        reflect(-world_view_dir, normal),
+        ROUGHNESS * f32(MAX_LOD)
+    ).rgb;
+    let brdf = textureSample(t_brdf, s_ibl, vec2<f32>(n_dot_v, ROUGHNESS)).rg;
+    let ambient_color = (1.0 - f) * irradiance;
+    let ambient_specular = prefiltered * (f * brdf.x + brdf.y);
     let light_dir = normalize(in.tangent_light_position - in.tangent_position);
     let view_dir = normalize(in.tangent_view_position - in.tangent_position);
     // https://learnopengl.com/Advanced-Lighting/Advanced-Lighting
@@ -123,10 +138,16 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     //let reflect_dir = reflect(-light_dir, in.world_normal);
     //let specular_strength = pow(max(dot(view_dir, reflect_dir), 0.0), 32.0);
 
-    let result = (ambient_color + diffuse_color + specular_color) * object_color.xyz;
+    let result = (ambient_color + diffuse_color + specular_color) * object_color.xyz + ambient_specular;
     //let result = ambient_color * object_color.xyz;
     //let result = diffuse_color * object_color.xyz;
     //let result = specular_color * object_color.xyz;
 
-    return vec4<f32>(result, object_color.a);
+    let alpha = object_color.a * material.opacity;
+#ifdef ALPHA_MASK
+    if (alpha < material.alpha_cutoff) {
+        discard;
+    }
+#endif
+    return vec4<f32>(result, alpha);
 }
@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use cgmath::Matrix4;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    animation::{AnimationClip, AnimationPlayer, Skeleton},
+    model::{self, Vertex},
+    reflect::Reflection,
+    render, resources,
+    shader::Shader,
+    texture,
+    vertex::{Instance, InstanceRaw},
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    /// Indices into `Skeleton::joints`.
+    pub joints: [u32; 4],
+    /// Summing to 1.
+    pub weights: [f32; 4],
+}
+
+impl model::Vertex for SkinnedVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x2,
+            2 => Float32x3,
+            3 => Uint32x4,
+            4 => Float32x4,
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBS,
+        }
+    }
+}
+
+pub struct SkinnedMesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_elements: u32,
+    pub material: usize,
+}
+
+/// A model whose vertices follow an animated skeleton, as loaded by
+/// `resources::load_gltf`.
+///
+/// The joint matrices are recomputed on the CPU by `update` and skinned on
+/// the GPU by `skinned.wgsl`, which reads them from a storage buffer. WebGL
+/// has no storage buffers in vertex shaders, so these can't be drawn there.
+pub struct SkinnedModel {
+    pub meshes: Vec<SkinnedMesh>,
+    /// Diffuse textures, bound like `texture::TextureBindGroup`.
+    pub materials: Vec<wgpu::BindGroup>,
+    pub skeleton: Skeleton,
+    pub clips: Vec<AnimationClip>,
+    pub player: AnimationPlayer,
+    joint_buffer: wgpu::Buffer,
+    pub joint_bind_group: wgpu::BindGroup,
+}
+
+impl SkinnedModel {
+    /// Reflected along with the other shaders so that it can share their
+    /// camera, light and texture bind groups.
+    pub fn shader() -> Shader<'static> {
+        Shader::new(Some("skinned.shader"), include_str!("skinned.wgsl"))
+            .define_value("CAMERA_GROUP", 1)
+            .define_value("LIGHT_GROUP", 2)
+            .define_value("JOINTS_GROUP", 3)
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        meshes: Vec<SkinnedMesh>,
+        materials: Vec<wgpu::BindGroup>,
+        skeleton: Skeleton,
+        clips: Vec<AnimationClip>,
+        joints_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let matrices = skeleton.joint_matrices(&skeleton.rest_pose());
+        let joint_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("skinned.joint_buffer"),
+            contents: bytemuck::cast_slice(&to_raw(&matrices)),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let joint_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: joints_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: joint_buffer.as_entire_binding(),
+            }],
+            label: Some("skinned.joint_bind_group"),
+        });
+
+        Self {
+            meshes,
+            materials,
+            skeleton,
+            clips,
+            player: AnimationPlayer::new(0),
+            joint_buffer,
+            joint_bind_group,
+        }
+    }
+
+    /// Advance the animation by `dt` and upload the new joint matrices.
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: instant::Duration) {
+        let pose = self
+            .player
+            .update(dt.as_secs_f32(), &self.skeleton, &self.clips);
+        let matrices = self.skeleton.joint_matrices(&pose);
+        queue.write_buffer(
+            &self.joint_buffer,
+            0,
+            bytemuck::cast_slice(&to_raw(&matrices)),
+        );
+    }
+
+    /// Draw with the camera at group 1 and the light at group 2 already set,
+    /// and the instances in vertex buffer slot 1.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instances: Range<u32>) {
+        render_pass.set_bind_group(3, &self.joint_bind_group, &[]);
+        for mesh in &self.meshes {
+            render_pass.set_bind_group(0, &self.materials[mesh.material], &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+        }
+    }
+}
+
+/// A `SkinnedModel` with the pipeline and the placement it's drawn with.
+pub struct SkinnedBundle {
+    pub model: SkinnedModel,
+    render_pipeline: wgpu::RenderPipeline,
+    instance_buffer: wgpu::Buffer,
+}
+
+impl SkinnedBundle {
+    /// Needs storage buffers in vertex shaders, see
+    /// `render::supports_vertex_storage`.
+    pub async fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        reflection: &Reflection,
+        texture_layout: &wgpu::BindGroupLayout,
+        file_name: &str,
+        instance: Instance,
+    ) -> anyhow::Result<Self> {
+        let shader = SkinnedModel::shader();
+        let joints_layout = reflection.create_bind_group_layout(
+            device,
+            &["joints"],
+            Some("joints_bind_group_layout"),
+        )?;
+        let model =
+            resources::load_gltf(file_name, device, queue, texture_layout, &joints_layout).await?;
+        let render_pipeline = render::create_render_pipeline(
+            device,
+            &reflection.create_pipeline_layout(device, &shader, Some("Skinned Pipeline Layout"))?,
+            config.format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &[SkinnedVertex::desc(), InstanceRaw::desc()],
+            &shader,
+            &render::PipelineOptions::default(),
+            Some("Skinned Render Pipeline"),
+        );
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinned Instance Buffer"),
+            contents: bytemuck::cast_slice(&[instance.to_raw()]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        Ok(Self {
+            model,
+            render_pipeline,
+            instance_buffer,
+        })
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: instant::Duration) {
+        self.model.update(queue, dt);
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        self.model.draw(render_pass, 0..1);
+    }
+}
+
+fn to_raw(matrices: &[Matrix4<f32>]) -> Vec<[[f32; 4]; 4]> {
+    matrices.iter().map(|&matrix| matrix.into()).collect()
+}
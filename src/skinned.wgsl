@@ -0,0 +1,81 @@
+// Meshes deformed by a skeleton, with one joint matrix per joint in a
+// storage buffer. Lit like `shader.wgsl`.
+#include "camera.wgsl"
+#include "light.wgsl"
+#include "instance_input.wgsl"
+
+// Matches `skinned::SkinnedVertex::desc()`.
+struct SkinnedVertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) joints: vec4<u32>,
+    @location(4) weights: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+}
+
+// Filled by `skinned::SkinnedModel::update`.
+@group(JOINTS_GROUP) @binding(0)
+var<storage, read> joints: array<mat4x4<f32>>;
+
+@vertex
+fn vs_main(
+    model: SkinnedVertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+
+    let skin = joints[model.joints.x] * model.weights.x
+        + joints[model.joints.y] * model.weights.y
+        + joints[model.joints.z] * model.weights.z
+        + joints[model.joints.w] * model.weights.w;
+    let skin_3x3 = mat3x3<f32>(skin[0].xyz, skin[1].xyz, skin[2].xyz);
+
+    let world_position = model_matrix * skin * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.tex_coords = model.tex_coords;
+    out.world_normal = normalize(normal_matrix * skin_3x3 * model.normal);
+    out.world_position = world_position.xyz;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let object_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let normal = normalize(in.world_normal);
+
+    let ambient_color = light.color * 0.1;
+
+    let light_dir = normalize(light.position - in.world_position);
+    let diffuse_color = light.color * max(dot(normal, light_dir), 0.0);
+
+    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
+    let half_dir = normalize(view_dir + light_dir);
+    let specular_color = light.color * pow(max(dot(normal, half_dir), 0.0), 32.0);
+
+    let result = (ambient_color + diffuse_color + specular_color) * object_color.rgb;
+    return vec4<f32>(result, object_color.a);
+}
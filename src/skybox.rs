@@ -0,0 +1,130 @@
+use cgmath::SquareMatrix;
+use wgpu::util::DeviceExt;
+
+use crate::{camera, reflect::Reflection, shader::Shader, texture::Texture};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkyboxUniform {
+    inv_view_proj: [[f32; 4]; 4],
+}
+
+/// A cubemap drawn behind everything else in the main pass.
+pub struct Skybox {
+    pub texture: Texture,
+    pub uniform: SkyboxUniform,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Skybox {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        texture: Texture,
+    ) -> anyhow::Result<Self> {
+        let shader = Shader::new(Some("skybox.shader"), include_str!("skybox.wgsl"));
+        let reflection = Reflection::new(&[&shader])?;
+        reflection.check_sizes(&[("sky", std::mem::size_of::<SkyboxUniform>())])?;
+
+        let uniform = SkyboxUniform {
+            inv_view_proj: cgmath::Matrix4::identity().into(),
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("skybox.buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let layout = reflection.create_bind_group_layout(
+            device,
+            &["t_sky", "s_sky", "sky"],
+            Some("skybox.bind_group_layout"),
+        )?;
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("skybox.bind_group"),
+        });
+
+        let pipeline_layout =
+            reflection.create_pipeline_layout(device, &shader, Some("skybox.pipeline_layout"))?;
+        let module = shader.create_module(device)?;
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("skybox.render_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            // Drawn at depth 1.0, so only where nothing else was drawn.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Ok(Self {
+            texture,
+            uniform,
+            buffer,
+            bind_group,
+            render_pipeline,
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &camera::Camera,
+        projection: &camera::Projection,
+    ) {
+        // Only the camera's orientation matters for the sky.
+        let mut view = camera.calc_matrix();
+        view.w = cgmath::Vector4::unit_w();
+        let view_proj = projection.calc_matrix() * view;
+        if let Some(inv_view_proj) = view_proj.invert() {
+            self.uniform.inv_view_proj = inv_view_proj.into();
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,32 @@
+struct SkyboxUniform {
+    inv_view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var t_sky: texture_cube<f32>;
+@group(0) @binding(1)
+var s_sky: sampler;
+@group(0) @binding(2)
+var<uniform> sky: SkyboxUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) clip: vec4<f32>,
+}
+
+// One triangle covering the screen, placed on the far plane.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 1.0, 1.0);
+    out.clip = out.clip_position;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The view matrix has no translation, so this is a direction from the camera.
+    let world = sky.inv_view_proj * in.clip;
+    let dir = normalize(world.xyz / world.w);
+    return textureSample(t_sky, s_sky, dir);
+}
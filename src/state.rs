@@ -1,18 +1,30 @@
-use std::{fs::File, io::Write};
+use std::{fs::File, io::Write, mem::size_of};
 
 use cgmath::prelude::*;
 use wgpu::util::DeviceExt;
 
 use crate::{
+    assets::{self, Handle},
     buffer, camera,
-    data::{INDICES, NUM_INSTANCES_PER_ROW, VERTICES},
-    depth, light,
-    model::{self, DrawLight, DrawModel, Vertex},
-    render::{self, RenderPass},
-    resources, texture,
+    debug_draw::{self, DebugDraw},
+    debug_view::{self, DebugViews},
+    data::{NUM_INSTANCES_PER_ROW, SHAPE},
+    depth, gui, hud, ibl, light, lod,
+    model::{self, AlphaMode, DrawLight, DrawModel, Vertex},
+    morph, particles, primitives, profiler,
+    reflect::Reflection,
+    render::{self, ComputePass, RenderPass},
+    resources,
+    shader::Shader,
+    skinned, skybox, terrain,
+    text::{GlyphAtlas, TextRenderer},
+    texture,
     vertex::{self, Instance, InstanceRaw},
 };
 
+/// Textures cycled with Tab, where "stone" selects the normal-mapped material.
+const TEXTURE_LABELS: [&str; 5] = ["tree.png", "gari.png", "baba.png", "moon-diffuse.png", "stone"];
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct KeyState {
     pub show_depth: bool,
@@ -24,6 +36,31 @@ pub struct KeyState {
     pub tab: bool,
     pub tab_index: usize,
     pub background: bool,
+    pub skybox: bool,
+    pub debug_view: debug_view::DebugView,
+    pub gizmos: bool,
+    /// Draws every mesh of the instanced models in this mode, instead of
+    /// the one its material asks for.
+    pub alpha_mode: Option<AlphaMode>,
+}
+
+impl KeyState {
+    /// One line per toggle for the HUD, with the key that changes it.
+    pub fn status(&self) -> Vec<String> {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        vec![
+            format!("[Tab] texture: {}", TEXTURE_LABELS[self.tab_index]),
+            format!("[V] view: {:?}", self.debug_view),
+            format!("[O] alpha: {}", alpha_mode_label(self.alpha_mode)),
+            format!("[Y] alt shape: {}", on_off(self.alt_shape)),
+            format!("[L] texture loop: {}", on_off(self.tex_loop)),
+            format!("[R] rotate: {}", on_off(self.rotate)),
+            format!("[Z] depth: {}", on_off(self.show_depth)),
+            format!("[B] background: {}", on_off(self.background)),
+            format!("[K] skybox: {}", on_off(self.skybox)),
+            format!("[G] gizmos: {}", on_off(self.gizmos)),
+        ]
+    }
 }
 
 #[rustfmt::skip]
@@ -36,27 +73,54 @@ pub struct State {
 
     pub config: wgpu::SurfaceConfiguration,
 
-    pub render_pipeline: wgpu::RenderPipeline,
-    pub material_render_pipeline: wgpu::RenderPipeline,
+    /// Indexed by `AlphaMode`.
+    pub render_pipelines: Vec<wgpu::RenderPipeline>,
+    pub material_render_pipelines: Vec<wgpu::RenderPipeline>,
+    pub assets: assets::AssetServer,
     pub texture_bind_group: texture::TextureBindGroup,
+    /// Indexed like `TEXTURE_LABELS`, up to "stone".
+    textures: Vec<Handle<texture::Texture>>,
 
-    pub vertex_buffer: wgpu::Buffer,
-    pub index_buffer: wgpu::Buffer,
-    pub num_indices: u32,
+    /// Drawn in place of `obj_model` with `KeyState::alt_shape`.
+    alt_mesh: model::Mesh,
     instances: Vec<Instance>,
+    instance_spacing: f32,
     instance_buffer: wgpu::Buffer,
+    /// `instances` back to front, for the blended pass.
+    blended_instance_buffer: wgpu::Buffer,
 
     pub camera_bundle: camera::CameraBundle,
     rotation_bundle: vertex::RotationBundle,
 
     depth_pass: depth::DepthPass,
-    obj_model: model::Model,
+    obj_model: Handle<model::Model>,
+    /// `None` where vertex shaders can't read storage buffers.
+    skinned: Option<skinned::SkinnedBundle>,
+    morph: Option<morph::MorphBundle>,
+    /// `None` without compute shaders.
+    particles: Option<particles::ParticleSystem>,
+    terrain: terrain::TerrainBundle,
+    /// One of each `primitives` shape, drawn with `debug_material`.
+    primitives: Vec<model::Mesh>,
+    primitive_instance_buffer: wgpu::Buffer,
+    /// Tori going off into the distance, drawn with `debug_material` at
+    /// their levels of detail.
+    lod_field: lod::LodInstances,
     pub keys: KeyState,
 
     light_bundle: light::LightBundle,
     light_render_pipeline: wgpu::RenderPipeline,
 
-    debug_material: model::Material,
+    debug_material: Handle<model::Material>,
+    skybox: skybox::Skybox,
+    ibl: ibl::Ibl,
+    debug_views: DebugViews,
+    pub debug_draw: DebugDraw,
+    /// The camera's view projection as the gizmos were turned on.
+    gizmo_frustum: Option<cgmath::Matrix4<f32>>,
+    pub hud: hud::Hud,
+    pub gui: gui::Gui,
+    pub profiler: profiler::Profiler,
 }
 
 impl State {
@@ -76,7 +140,10 @@ impl State {
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
+                    // Used for the wireframe debug view and the profiler when
+                    // available.
+                    features: adapter.features()
+                        & (wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::TIMESTAMP_QUERY),
                     // WebGL doesn't support all of wgpu's features, so if
                     // we're building for the web we'll have to disable some.
                     limits: if cfg!(target_arch = "wasm32") {
@@ -99,164 +166,247 @@ impl State {
             present_mode: wgpu::PresentMode::Fifo,
         };
         surface.configure(&device, &config);
+        // For skinning and morph targets.
+        let vertex_storage = render::supports_vertex_storage(&adapter, &device);
+        let compute = render::supports_compute(&adapter, &device);
+
+        let shaders = alpha_variants(
+            ["normal shader", "normal shader.mask", "normal shader.blend"],
+            |label| {
+                Shader::new(Some(label), include_str!("shader.wgsl"))
+                    .define_value("CAMERA_GROUP", 1)
+                    .define_value("ROTATION_GROUP", 2)
+                    .define_value("LIGHT_GROUP", 3)
+            },
+        );
+        let material_shaders = alpha_variants(
+            [
+                "material shader",
+                "material shader.mask",
+                "material shader.blend",
+            ],
+            |label| {
+                Shader::new(Some(label), include_str!("shader_mtl.wgsl"))
+                    .define("TANGENTS")
+                    .define_value("CAMERA_GROUP", 1)
+                    .define_value("ROTATION_GROUP", 2)
+                    .define_value("LIGHT_GROUP", 3)
+                    .define_value("IBL_GROUP", 3)
+                    .define_value("MAX_LOD", ibl::Ibl::PREFILTERED_MIPS - 1)
+            },
+        );
+        let light_shader = Shader::new(Some("Light Shader"), include_str!("light.wgsl"))
+            .define_value("CAMERA_GROUP", 0)
+            .define_value("LIGHT_GROUP", 1);
 
-        let texture_bind_group = texture::TextureBindGroup::from_files(
+        let debug_shaders = DebugViews::shaders(&device);
+        let debug_draw_shader = DebugDraw::shader();
+        let skinned_shader = skinned::SkinnedModel::shader();
+        let morph_shader = morph::MorphBundle::shader();
+        let particle_shaders = particles::ParticleSystem::shaders();
+        let reflection = Reflection::new(
+            &shaders
+                .iter()
+                .chain(&material_shaders)
+                .chain([
+                    &light_shader,
+                    &debug_draw_shader,
+                    &skinned_shader,
+                    &morph_shader,
+                ])
+                .chain(&particle_shaders)
+                .chain(&debug_shaders)
+                .collect::<Vec<_>>(),
+        )?;
+        reflection.check_sizes(&[
+            ("camera", size_of::<camera::CameraUniform>()),
+            ("rotation", size_of::<vertex::RotationUniform>()),
+            ("light", size_of::<light::LightUniform>()),
+            ("material", size_of::<model::MaterialUniform>()),
+        ])?;
+
+        let texture_bind_group_layout = reflection.create_bind_group_layout(
+            &device,
+            &["t_diffuse", "s_diffuse"],
+            Some("texture_bind_group_layout"),
+        )?;
+        let skinned = if vertex_storage {
+            // Standing on the cube in the middle of the grid.
+            let instance = Instance {
+                position: cgmath::Vector3::unit_y(),
+                rotation: cgmath::Quaternion::one(),
+            };
+            Some(
+                skinned::SkinnedBundle::new(
+                    &device,
+                    &queue,
+                    &config,
+                    &reflection,
+                    &texture_bind_group_layout,
+                    "column.glb",
+                    instance,
+                )
+                .await?,
+            )
+        } else {
+            None
+        };
+        // Textures and models load in the background, shown by placeholders
+        // until they're done.
+        let mut assets = assets::AssetServer::new(
             &device,
             &queue,
-            vec!["gari.png", "tree.png", "baba.png", "moon-diffuse.png"],
-        )
-        .await?;
+            reflection.create_bind_group_layout(
+                &device,
+                &["t_diffuse", "s_diffuse", "t_normal", "s_normal", "material"],
+                Some("material_bind_group_layout"),
+            )?,
+        )?;
+        let (texture_bind_group, textures) = texture::TextureBindGroup::from_files(
+            &device,
+            &queue,
+            texture_bind_group_layout,
+            &mut assets,
+            &TEXTURE_LABELS[..TEXTURE_LABELS.len() - 1],
+        )?;
+        // The cube and its textures, from an archive.
+        resources::mount("pack", "cube.zip").await?;
+        let obj_model = assets.request_model("pack/cube.obj");
 
-        let light_bundle = light::LightBundle::new(&device, [2.0, 2.0, 2.0], [1.0, 1.0, 1.0]);
-        let camera_bundle = camera::CameraBundle::new(&device, &config);
-        let rotation_bundle = vertex::RotationBundle::new(&device);
-
-        // Create instances.
-        const SPACE_BETWEEN: f32 = 3.0;
-        let instances = (0..NUM_INSTANCES_PER_ROW)
-            .flat_map(|z| {
-                (0..NUM_INSTANCES_PER_ROW).map(move |x| {
-                    let x = SPACE_BETWEEN * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-                    let z = SPACE_BETWEEN * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-                    let position = cgmath::Vector3 { x, y: 0.0, z };
-
-                    let rotation = if position.is_zero() {
-                        // this is needed so an object at (0, 0, 0) won't get scaled to zero
-                        // as Quaternions can effect scale if they're not created correctly
-                        cgmath::Quaternion::from_axis_angle(
-                            cgmath::Vector3::unit_z(),
-                            cgmath::Deg(0.0),
-                        )
-                    } else {
-                        cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
-                    };
-                    /*let rotation = cgmath::Quaternion::from_axis_angle(
-                        (0.0, 1.0, 0.0).into(),
-                        cgmath::Deg(180.0),
-                    );*/
-
-                    Instance { position, rotation }
-                })
-            })
-            .collect::<Vec<_>>();
+        let light_bundle = light::LightBundle::new(
+            &device,
+            &reflection.create_bind_group_layout(&device, &["light"], Some("light_bind_group_layout"))?,
+            [2.0, 2.0, 2.0],
+            [1.0, 1.0, 1.0],
+        );
+        let camera_bundle = camera::CameraBundle::new(
+            &device,
+            &config,
+            &reflection.create_bind_group_layout(
+                &device,
+                &["camera"],
+                Some("camera_bind_group_layout"),
+            )?,
+        );
+        let rotation_bundle = vertex::RotationBundle::new(
+            &device,
+            &reflection.create_bind_group_layout(
+                &device,
+                &["rotation"],
+                Some("rotation_bind_group_layout"),
+            )?,
+        );
+
+        let instance_spacing = 3.0;
+        let instances = create_instances(instance_spacing);
         let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Instance Buffer"),
             contents: bytemuck::cast_slice(&instance_data),
-            usage: wgpu::BufferUsages::VERTEX,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        // Written by `update` while anything blends.
+        let blended_instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blended Instance Buffer"),
+            contents: bytemuck::cast_slice(&instance_data),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         });
 
         let depth_pass = depth::DepthPass::new(&device, &config);
 
-        let render_pipeline = {
-            let render_pipeline_layout =
-                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Render Pipeline Layout"),
-                    bind_group_layouts: &[
-                        &texture_bind_group.layout,
-                        &camera_bundle.layout,
-                        &rotation_bundle.layout,
-                        &light_bundle.layout,
-                    ],
-                    push_constant_ranges: &[],
-                });
+        let render_pipelines = create_alpha_pipelines(&device, &config, &reflection, &shaders)?;
 
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("normal shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-            };
-            // ^-OR: let shader = device.create_shader_module(include_wgsl!("shader.wgsl"));
+        let material_render_pipelines =
+            create_alpha_pipelines(&device, &config, &reflection, &material_shaders)?;
 
-            render::create_render_pipeline(
-                &device,
-                &render_pipeline_layout,
-                config.format,
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[model::ModelVertex::desc(), InstanceRaw::desc()],
-                shader,
-                Some("Main Render Pipeline"),
+        let morph = if vertex_storage {
+            // In a row behind the grid.
+            let instances = (-2..=2)
+                .map(|x| Instance {
+                    position: cgmath::Vector3::new(x as f32 * 3.0, 0.0, -18.0),
+                    rotation: cgmath::Quaternion::one(),
+                })
+                .collect::<Vec<_>>();
+            Some(
+                morph::MorphBundle::new(
+                    &device,
+                    &queue,
+                    &config,
+                    &reflection,
+                    &assets.material_layout,
+                    "blob.glb",
+                    &instances,
+                )
+                .await?,
             )
+        } else {
+            None
         };
 
-        let material_bind_group_layout = device.create_bind_group_layout(&model::Material::desc());
-
-        let material_render_pipeline = {
-            let render_pipeline_layout =
-                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Render Pipeline Layout"),
-                    bind_group_layouts: &[
-                        &material_bind_group_layout,
-                        &camera_bundle.layout,
-                        &rotation_bundle.layout,
-                        &light_bundle.layout,
-                    ],
-                    push_constant_ranges: &[],
-                });
-
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("normal shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("shader_mtl.wgsl").into()),
-            };
-            // ^-OR: let shader = device.create_shader_module(include_wgsl!("shader.wgsl"));
+        // The world map under the grid, and the globe behind it.
+        let terrain = terrain::TerrainBundle::new(
+            &device,
+            &queue,
+            &config,
+            &reflection,
+            &assets.material_layout,
+            &material_shaders[0],
+            "earth-dem.png",
+            (
+                Instance {
+                    position: cgmath::Vector3::new(0.0, -4.0, 0.0),
+                    rotation: cgmath::Quaternion::one(),
+                },
+                &terrain::TerrainOptions {
+                    vertical_scale: 3.0,
+                    tile_size: 20.0,
+                    tiles: [4, 2],
+                    tile_resolution: 64,
+                },
+            ),
+            (
+                Instance {
+                    position: cgmath::Vector3::new(0.0, 8.0, -40.0),
+                    rotation: cgmath::Quaternion::one(),
+                },
+                &terrain::SphereOptions {
+                    radius: 6.0,
+                    vertical_scale: 0.5,
+                    segments: 256,
+                },
+            ),
+        )
+        .await?;
 
-            render::create_render_pipeline(
+        let particles = if compute {
+            Some(particles::ParticleSystem::new(
                 &device,
-                &render_pipeline_layout,
-                config.format,
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[model::ModelVertex::desc(), InstanceRaw::desc()],
-                shader,
-                Some("Main Render Pipeline"),
-            )
+                &config,
+                &reflection,
+                particles::sparks(cgmath::Point3::new(-6.0, 1.0, 3.0))?,
+            )?)
+        } else {
+            None
         };
 
-        let light_render_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Light Pipeline Layout"),
-                bind_group_layouts: &[
-                    //&texture_bind_group.layout,
-                    &camera_bundle.layout,
-                    //&rotation_bundle.layout,
-                    &light_bundle.layout,
-                ],
-
-                push_constant_ranges: &[],
-            });
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Light Shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("light.wgsl").into()),
-            };
+        let debug_views = DebugViews::new(&device, &config, &reflection)?;
+        let debug_draw = DebugDraw::new(&device, &config, &reflection)?;
 
-            render::create_render_pipeline(
-                &device,
-                &layout,
-                config.format,
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[model::ModelVertex::desc()],
-                shader,
-                Some("Light Render Pipeline"),
-            )
-        };
+        let light_render_pipeline = render::create_render_pipeline(
+            &device,
+            &reflection.create_pipeline_layout(&device, &light_shader, Some("Light Pipeline Layout"))?,
+            config.format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &[model::ModelVertex::desc()],
+            &light_shader,
+            &render::PipelineOptions::default(),
+            Some("Light Render Pipeline"),
+        );
 
         // Buffers.
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-        let num_indices = INDICES.len() as u32;
+        let alt_mesh = primitives::fan(SHAPE).create_mesh(&device, "alt shape", 0);
 
-        let obj_model =
-            resources::load_model("cube.obj", &device, &queue, &material_bind_group_layout)
-                .await
-                .unwrap();
-
-        let debug_material = {
+        let debug_material = assets.materials.add("alt-material", {
             let diffuse_bytes = include_bytes!("../res/cobble-diffuse.png");
             let normal_bytes = include_bytes!("../res/cobble-normal.png");
 
@@ -282,9 +432,94 @@ impl State {
                 "alt-material",
                 diffuse_texture,
                 normal_texture,
-                &material_bind_group_layout,
+                AlphaMode::Opaque,
+                1.0,
+                &assets.material_layout,
             )
-        };
+        });
+
+        // In a row behind the morph targets.
+        let primitives = [
+            ("plane", primitives::plane([1.6, 1.6], [4, 4])),
+            ("cube", primitives::cube(1.6, 2)),
+            ("uv sphere", primitives::uv_sphere(1.0, 32, 16)),
+            ("icosphere", primitives::icosphere(1.0, 3)),
+            ("cylinder", primitives::cylinder(0.8, 2.0, 32, 2)),
+            ("cone", primitives::cone(0.9, 2.0, 32, 4)),
+            ("torus", primitives::torus(0.7, 0.3, 48, 24)),
+            ("capsule", primitives::capsule(0.6, 0.8, 32, 24)),
+        ]
+        .iter()
+        .map(|(name, data)| data.create_mesh(&device, name, 0))
+        .collect::<Vec<_>>();
+        let primitive_instances = (0..primitives.len())
+            .map(|i| {
+                Instance {
+                    position: cgmath::Vector3::new(i as f32 * 3.0 - 10.5, 1.0, -24.0),
+                    rotation: cgmath::Quaternion::one(),
+                }
+                .to_raw()
+            })
+            .collect::<Vec<_>>();
+        let primitive_instance_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Primitive Instance Buffer"),
+                contents: bytemuck::cast_slice(&primitive_instances),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        // Behind the primitives.
+        let lod_field = lod::LodInstances::new(
+            &device,
+            &primitives::torus(0.7, 0.3, 96, 48),
+            "lod torus",
+            0,
+            lod::LodOptions {
+                // Seams keep the coarsest level from going much lower.
+                ratios: vec![0.25, 0.06],
+                screen_sizes: vec![0.1, 0.04],
+                hysteresis: 0.1,
+            },
+            (0..16 * 8)
+                .map(|i| Instance {
+                    position: cgmath::Vector3::new(
+                        (i % 8) as f32 * 4.0 - 14.0,
+                        0.0,
+                        -30.0 - (i / 8) as f32 * 4.0,
+                    ),
+                    rotation: cgmath::Quaternion::from_angle_x(cgmath::Deg(60.0)),
+                })
+                .collect(),
+        );
+
+        let skybox = skybox::Skybox::new(
+            &device,
+            &config,
+            resources::load_cubemap(&["stars.png"], &device, &queue).await?,
+        )?;
+        let hud = hud::Hud::new(TextRenderer::new(
+            &device,
+            &config,
+            GlyphAtlas::new(
+                &device,
+                &queue,
+                resources::load_binary("DejaVuSansMono.ttf").await?,
+                16.0,
+            )?,
+        )?);
+        let gui = gui::Gui::new(&device, &config, window.scale_factor() as f32)?;
+        let profiler = profiler::Profiler::new(&device, &queue);
+        let ibl = ibl::Ibl::new(
+            &device,
+            &queue,
+            &skybox.texture,
+            &reflection.create_bind_group_layout(
+                &device,
+                &["light", "t_irradiance", "t_prefiltered", "t_brdf", "s_ibl"],
+                Some("ibl_bind_group_layout"),
+            )?,
+            &light_bundle,
+        )?;
 
         Ok(Self {
             surface,
@@ -293,26 +528,48 @@ impl State {
             config,
             size,
             clear_color,
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
+            render_pipelines,
+            alt_mesh,
+            assets,
             texture_bind_group,
+            textures,
             camera_bundle,
             rotation_bundle,
             instances,
+            instance_spacing,
             instance_buffer,
+            blended_instance_buffer,
             depth_pass,
             obj_model,
-            keys: KeyState::default(),
+            skinned,
+            morph,
+            particles,
+            terrain,
+            primitives,
+            primitive_instance_buffer,
+            lod_field,
+            keys: KeyState {
+                skybox: true,
+                ..Default::default()
+            },
             light_bundle,
             light_render_pipeline,
-            material_render_pipeline,
+            material_render_pipelines,
             debug_material,
+            skybox,
+            ibl,
+            debug_views,
+            debug_draw,
+            gizmo_frustum: None,
+            hud,
+            gui,
+            profiler,
         })
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let start = instant::Instant::now();
+        self.profiler.begin_frame(&self.device);
         let output = self.surface.get_current_texture()?;
         let view = output
             .texture
@@ -322,90 +579,118 @@ impl State {
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.clear_color),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_pass.texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
 
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-
-            let labels = [
-                "tree.png",
-                "gari.png",
-                "baba.png",
-                "moon-diffuse.png",
-                "stone",
-            ];
-            if self.keys.tab {
-                self.keys.tab = false;
-                self.keys.tab_index = (self.keys.tab_index + 1) % labels.len();
-            }
+        let labels = TEXTURE_LABELS;
+        if self.keys.tab {
+            self.keys.tab = false;
+            self.keys.tab_index = (self.keys.tab_index + 1) % labels.len();
+        }
+
+        if let Some(particles) = &mut self.particles {
+            self.profiler.begin_pass(&mut encoder, "particles.compute");
+            particles.compute(&mut encoder);
+            self.profiler.end_pass(&mut encoder);
+        }
+
+        // The light and the models are separate passes so that each can be
+        // timed by the profiler.
+        self.profiler.begin_pass(&mut encoder, "light");
+        {
+            let mut render_pass = self.begin_main_pass(&mut encoder, &view, true);
 
             render_pass.set_bind_group(0, &self.camera_bundle.bind_group, &[]);
             render_pass.set_bind_group(1, &self.light_bundle.bind_group, &[]);
 
             render_pass.set_pipeline(&self.light_render_pipeline);
             render_pass.draw_light_model(
-                &self.obj_model,
+                self.assets.model(&self.obj_model),
                 &self.camera_bundle.bind_group,
                 &self.light_bundle.bind_group,
             );
+        }
+        self.profiler.end_pass(&mut encoder);
 
-            if labels[self.keys.tab_index] == "stone" {
-                let mesh = &self.obj_model.meshes[0];
-                let _material = &self.obj_model.materials[mesh.material];
-                // DEBUG MATERIAL
-                let material = &self.debug_material;
-                render_pass.set_pipeline(&self.material_render_pipeline);
-                render_pass.set_bind_group(0, &material.bind_group, &[]);
-            } else {
-                render_pass.set_pipeline(&self.render_pipeline);
-                render_pass.set_bind_group(
-                    0,
-                    self.texture_bind_group.get(labels[self.keys.tab_index]),
-                    &[],
-                );
-            };
-            render_pass.set_bind_group(1, &self.camera_bundle.bind_group, &[]);
-            render_pass.set_bind_group(2, &self.rotation_bundle.bind_group, &[]);
-            render_pass.set_bind_group(3, &self.light_bundle.bind_group, &[]);
-
-            if self.keys.alt_shape {
-                render_pass
-                    .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-                render_pass.draw_indexed(9..self.num_indices, 5, 0..self.instances.len() as u32);
-            } else {
-                render_pass.draw_model_instanced(
-                    &self.obj_model,
-                    0..self.instances.len() as u32,
+        self.profiler.begin_pass(&mut encoder, "models");
+        {
+            let mut render_pass = self.begin_main_pass(&mut encoder, &view, false);
+            self.draw_models(&mut render_pass, false);
+            if let Some(skinned) = &self.skinned {
+                skinned.draw(
+                    &mut render_pass,
                     &self.camera_bundle.bind_group,
                     &self.light_bundle.bind_group,
                 );
             }
+            if let Some(morph) = &self.morph {
+                morph.draw(
+                    &mut render_pass,
+                    &self.camera_bundle.bind_group,
+                    &self.ibl.bind_group,
+                );
+            }
+            self.terrain.draw(
+                &mut render_pass,
+                &self.camera_bundle.bind_group,
+                &self.ibl.bind_group,
+            );
+            self.draw_primitives(&mut render_pass);
+            if self.keys.skybox {
+                self.skybox.draw(&mut render_pass);
+            }
+            // Blended meshes go over everything opaque, the skybox included.
+            self.draw_models(&mut render_pass, true);
+        }
+        self.profiler.end_pass(&mut encoder);
+
+        if let Some(particles) = &self.particles {
+            self.profiler.begin_pass(&mut encoder, "particles");
+            {
+                let mut render_pass = self.begin_main_pass(&mut encoder, &view, false);
+                particles.draw(&mut render_pass, &self.camera_bundle.bind_group);
+            }
+            self.profiler.end_pass(&mut encoder);
         }
 
-        // Show depth mask in corner of screen.
+        self.profiler.begin_pass(&mut encoder, "debug_draw");
+        self.debug_draw.render(
+            &self.device,
+            &self.queue,
+            &view,
+            &self.depth_pass.texture,
+            &self.camera_bundle.bind_group,
+            &mut encoder,
+        );
+        self.profiler.end_pass(&mut encoder);
+
+        // Show the depth buffer in a corner of the screen.
         if self.keys.show_depth {
+            self.profiler.begin_pass(&mut encoder, "depth");
             self.depth_pass.render(&view, &mut encoder);
+            self.profiler.end_pass(&mut encoder);
         }
 
+        let position = self.camera_bundle.camera.position;
+        let mut status = vec![format!(
+            "camera: {:.1} {:.1} {:.1}",
+            position.x, position.y, position.z
+        )];
+        let progress = self.assets.progress();
+        if !progress.is_done() {
+            status.push(format!("loading: {}", progress));
+        }
+        status.extend(self.keys.status());
+        status.extend(self.profiler.summary());
+        self.profiler.begin_pass(&mut encoder, "hud");
+        self.hud
+            .render(&self.device, &self.queue, &view, &mut encoder, &status);
+        self.profiler.end_pass(&mut encoder);
+
+        self.profiler.begin_pass(&mut encoder, "gui");
+        self.gui
+            .render(&self.device, &self.queue, &view, &mut encoder);
+        self.profiler.end_pass(&mut encoder);
+        self.profiler.resolve(&mut encoder);
+
         // Screenshot.  FIXME: too slow and need to convert colorspace
         if self.keys.screenshot {
             self.keys.screenshot = false;
@@ -414,12 +699,174 @@ impl State {
             let command_buffer = encoder.finish();
             self.queue.submit(Some(command_buffer));
         }
+        self.profiler.after_submit();
 
         output.present();
+        self.profiler.record_cpu("render", start);
 
         Ok(())
     }
 
+    /// Draw the meshes of the instanced models that blend when `blended`
+    /// is set and the rest otherwise, with the selected texture or material.
+    fn draw_models<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, blended: bool) {
+        let instance_buffer = if blended {
+            &self.blended_instance_buffer
+        } else {
+            &self.instance_buffer
+        };
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        render_pass.set_bind_group(1, &self.camera_bundle.bind_group, &[]);
+        render_pass.set_bind_group(2, &self.rotation_bundle.bind_group, &[]);
+        let obj_model = self.assets.model(&self.obj_model);
+        let instances = 0..self.instances.len() as u32;
+
+        // The debug views only cover the model.
+        let debug_view = if self.keys.alt_shape {
+            debug_view::DebugView::Shaded
+        } else {
+            self.keys.debug_view
+        };
+        if debug_view.replaces_shading() {
+            // Drawn by `debug_views` below.
+        } else if self.keys.alt_shape {
+            if ModelStyle::set(self.model_style(None), render_pass, blended) {
+                let mesh = &self.alt_mesh;
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+                render_pass.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+            }
+        } else {
+            for mesh in &obj_model.meshes {
+                let material = &obj_model.materials[mesh.material];
+                if ModelStyle::set(self.model_style(Some(material)), render_pass, blended) {
+                    render_pass.draw_mesh_instanced(
+                        mesh,
+                        material,
+                        instances.clone(),
+                        &self.camera_bundle.bind_group,
+                        &self.light_bundle.bind_group,
+                    );
+                }
+            }
+        }
+        if !blended {
+            self.debug_views
+                .draw(debug_view, render_pass, obj_model, instances);
+        }
+    }
+
+    /// How to draw a mesh of the instanced models made of `material`, or the
+    /// alternative shape without one. `None` while the stone material loads.
+    fn model_style<'a>(&'a self, material: Option<&'a model::Material>) -> Option<ModelStyle<'a>> {
+        let with_material = |material: &'a model::Material, alpha_mode| ModelStyle {
+            pipelines: &self.material_render_pipelines,
+            material: &material.bind_group,
+            light: &self.ibl.bind_group,
+            alpha_mode,
+        };
+        match (self.keys.alpha_mode, material) {
+            // With its own material, whose opacity goes with blending.
+            (None, Some(material)) if material.alpha_mode == AlphaMode::Blend => {
+                Some(with_material(material, AlphaMode::Blend))
+            }
+            (alpha_mode, _) if TEXTURE_LABELS[self.keys.tab_index] == "stone" => {
+                let material = self.assets.materials.get(&self.debug_material)?;
+                Some(with_material(
+                    material,
+                    alpha_mode.unwrap_or(material.alpha_mode),
+                ))
+            }
+            // The textures cycled with Tab stand in for the material's own,
+            // so they're cut out by their alpha.
+            (alpha_mode, _) => Some(ModelStyle {
+                pipelines: &self.render_pipelines,
+                material: self
+                    .texture_bind_group
+                    .get(&self.textures[self.keys.tab_index]),
+                light: &self.light_bundle.bind_group,
+                alpha_mode: alpha_mode.unwrap_or(AlphaMode::Mask),
+            }),
+        }
+    }
+
+    /// Whether any of the instanced models is drawn blended.
+    fn blends_models(&self) -> bool {
+        let blends = |style: Option<ModelStyle>| {
+            style.is_some_and(|style| style.alpha_mode == AlphaMode::Blend)
+        };
+        if self.keys.alt_shape {
+            return blends(self.model_style(None));
+        }
+        !self.keys.debug_view.replaces_shading()
+            && self
+                .assets
+                .model(&self.obj_model)
+                .materials
+                .iter()
+                .any(|material| blends(self.model_style(Some(material))))
+    }
+
+    fn draw_primitives<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let Some(material) = self.assets.materials.get(&self.debug_material) else {
+            return;
+        };
+        render_pass.set_pipeline(&self.material_render_pipelines[AlphaMode::Opaque as usize]);
+        render_pass.set_bind_group(0, &material.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.camera_bundle.bind_group, &[]);
+        render_pass.set_bind_group(2, &self.rotation_bundle.bind_group, &[]);
+        render_pass.set_bind_group(3, &self.ibl.bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.primitive_instance_buffer.slice(..));
+        for (i, mesh) in self.primitives.iter().enumerate() {
+            render_pass.draw_mesh_instanced(
+                mesh,
+                material,
+                i as u32..i as u32 + 1,
+                &self.camera_bundle.bind_group,
+                &self.ibl.bind_group,
+            );
+        }
+        // With the same material and bind groups.
+        self.lod_field.draw(render_pass);
+    }
+
+    /// Begin a pass over the surface and the depth buffer, clearing both
+    /// when `clear` is set and drawing over them otherwise.
+    fn begin_main_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        view: &'a wgpu::TextureView,
+        clear: bool,
+    ) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: if clear {
+                        wgpu::LoadOp::Clear(self.clear_color)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_pass.texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: if clear {
+                        wgpu::LoadOp::Clear(1.0)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         //println!("RESIZE: {:?}", new_size);
         if new_size.width > 0 && new_size.height > 0 {
@@ -429,18 +876,303 @@ impl State {
             self.surface.configure(&self.device, &self.config);
         }
         self.depth_pass.resize(&self.device, &self.config);
+        if let Some(particles) = &mut self.particles {
+            particles.resize(&self.device, &self.config);
+        }
         self.camera_bundle
             .projection
             .resize(new_size.width, new_size.height);
+        self.hud.text.resize(&self.queue, &self.config);
+        self.gui.resize(&self.queue, &self.config);
     }
 
     pub fn update(&mut self, dt: instant::Duration) {
+        let start = instant::Instant::now();
+        self.debug_draw.begin_frame();
+        self.update_gui();
         self.camera_bundle.update(&self.queue, dt);
+        self.hud.update(dt);
         if self.keys.rotate {
             self.rotation_bundle.update(&self.queue);
         }
+        self.depth_pass
+            .set_projection(&self.camera_bundle.projection);
         self.depth_pass.update(&self.queue);
         self.light_bundle.update(&self.queue, dt);
+        if let Some(skinned) = &mut self.skinned {
+            skinned.update(&self.queue, dt);
+        }
+        if let Some(morph) = &mut self.morph {
+            morph.update(&self.queue, dt);
+        }
+        if let Some(particles) = &mut self.particles {
+            particles.advance(dt, &self.camera_bundle.camera);
+            particles.update(&self.queue);
+        }
+        self.skybox.update(
+            &self.queue,
+            &self.camera_bundle.camera,
+            &self.camera_bundle.projection,
+        );
+        self.lod_field.update(
+            &self.queue,
+            &self.camera_bundle.camera,
+            &self.camera_bundle.projection,
+        );
+
+        // Blended instances have to be drawn back to front.
+        if self.blends_models() {
+            let eye = self.camera_bundle.camera.position.to_vec();
+            let mut sorted = self.instances.iter().collect::<Vec<_>>();
+            sorted.sort_by(|a, b| {
+                (b.position - eye)
+                    .magnitude2()
+                    .total_cmp(&(a.position - eye).magnitude2())
+            });
+            let instance_data = sorted.into_iter().map(Instance::to_raw).collect::<Vec<_>>();
+            self.queue.write_buffer(
+                &self.blended_instance_buffer,
+                0,
+                bytemuck::cast_slice(&instance_data),
+            );
+        }
+
+        if self.keys.gizmos {
+            let light = cgmath::Point3::from(self.light_bundle.uniform.position);
+            self.debug_draw.axes(cgmath::Point3::origin(), 1.0);
+            self.debug_draw.sphere(light, 0.5, self.light_bundle.uniform.color);
+            self.debug_draw
+                .line(cgmath::Point3::origin(), light, debug_draw::GREEN);
+
+            // Bounds of the instanced cubes, whatever their rotation.
+            let extent = cgmath::Vector3::from_value(3f32.sqrt());
+            let (min, max) = self.instances.iter().fold(
+                (
+                    cgmath::Point3::from_value(f32::MAX),
+                    cgmath::Point3::from_value(f32::MIN),
+                ),
+                |(min, max), instance| {
+                    let center = cgmath::Point3::from_vec(instance.position);
+                    (
+                        min.zip(center - extent, f32::min),
+                        max.zip(center + extent, f32::max),
+                    )
+                },
+            );
+            self.debug_draw.aabb(min, max, [1.0, 1.0, 0.0]);
+
+            // Left where the camera was, to fly out of and look back at.
+            let camera = &self.camera_bundle;
+            let frustum = *self.gizmo_frustum.get_or_insert_with(|| {
+                camera.projection.calc_matrix() * camera.camera.calc_matrix()
+            });
+            self.debug_draw.frustum(frustum, [0.0, 1.0, 1.0]);
+        } else {
+            self.gizmo_frustum = None;
+        }
+        self.assets.update(&self.device, &self.queue);
+        self.texture_bind_group
+            .update(&self.device, &self.assets.textures);
+        self.assets.collect_garbage();
+        self.profiler.record_cpu("update", start);
+    }
+
+    /// Show the parameter panel and apply whatever was changed in it.
+    fn update_gui(&mut self) {
+        let keys = &mut self.keys;
+        let light = &mut self.light_bundle.uniform;
+        let controller = &mut self.camera_bundle.controller;
+        let projection = &mut self.camera_bundle.projection;
+        let depth_pass = &mut self.depth_pass;
+        let mut spacing = self.instance_spacing;
+        let mut opacity = self
+            .assets
+            .materials
+            .get(&self.debug_material)
+            .map(|material| material.uniform.opacity);
+        let skinned = self.skinned.as_mut().map(|skinned| &mut skinned.model);
+        let emitter = self
+            .particles
+            .as_mut()
+            .map(|particles| &mut particles.emitter);
+        let lod_field = &mut self.lod_field;
+
+        self.gui.run(|ctx| {
+            egui::Window::new("Parameters")
+                .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+                .resizable(false)
+                .show(ctx, |ui| {
+                    egui::Grid::new("parameters").num_columns(2).show(ui, |ui| {
+                        ui.label("Texture");
+                        egui::ComboBox::from_id_source("texture")
+                            .selected_text(TEXTURE_LABELS[keys.tab_index])
+                            .show_ui(ui, |ui| {
+                                for (i, label) in TEXTURE_LABELS.iter().enumerate() {
+                                    ui.selectable_value(&mut keys.tab_index, i, *label);
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Alpha");
+                        egui::ComboBox::from_id_source("alpha")
+                            .selected_text(alpha_mode_label(keys.alpha_mode))
+                            .show_ui(ui, |ui| {
+                                let modes = AlphaMode::ALL.map(Some);
+                                for mode in std::iter::once(None).chain(modes) {
+                                    ui.selectable_value(
+                                        &mut keys.alpha_mode,
+                                        mode,
+                                        alpha_mode_label(mode),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        if let Some(opacity) = &mut opacity {
+                            ui.label("Stone opacity");
+                            ui.add(egui::Slider::new(opacity, 0.0..=1.0));
+                            ui.end_row();
+                        }
+
+                        ui.label("Light color");
+                        ui.color_edit_button_rgb(&mut light.color);
+                        ui.end_row();
+
+                        ui.label("Camera speed");
+                        ui.add(egui::Slider::new(&mut controller.speed, 0.5..=20.0));
+                        ui.end_row();
+
+                        ui.label("Mouse sensitivity");
+                        ui.add(egui::Slider::new(&mut controller.sensitivity, 0.05..=2.0));
+                        ui.end_row();
+
+                        ui.label("Field of view");
+                        let mut fovy = cgmath::Deg::from(projection.fovy).0;
+                        if ui
+                            .add(egui::Slider::new(&mut fovy, 20.0..=120.0).suffix("°"))
+                            .changed()
+                        {
+                            projection.fovy = cgmath::Deg(fovy).into();
+                        }
+                        ui.end_row();
+
+                        ui.label("Instance spacing");
+                        ui.add(egui::Slider::new(&mut spacing, 1.5..=8.0));
+                        ui.end_row();
+
+                        ui.label("Depth colormap");
+                        egui::ComboBox::from_id_source("colormap")
+                            .selected_text(format!("{:?}", depth_pass.colormap))
+                            .show_ui(ui, |ui| {
+                                for colormap in depth::Colormap::ALL {
+                                    ui.selectable_value(
+                                        &mut depth_pass.colormap,
+                                        colormap,
+                                        format!("{:?}", colormap),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Depth range");
+                        ui.horizontal(|ui| {
+                            let [min, max] = &mut depth_pass.range;
+                            for value in [min, max] {
+                                ui.add(
+                                    egui::DragValue::new(value)
+                                        .speed(0.1)
+                                        .clamp_range(0.0..=100.0),
+                                );
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Depth overlay");
+                        ui.horizontal(|ui| {
+                            let [x, y] = &mut depth_pass.position;
+                            let [width, height] = &mut depth_pass.size;
+                            for value in [x, y, width, height] {
+                                ui.add(
+                                    egui::DragValue::new(value)
+                                        .speed(0.01)
+                                        .clamp_range(0.0..=1.0),
+                                );
+                            }
+                        });
+                        ui.end_row();
+
+                        if let Some(skinned) = skinned {
+                            ui.label("Animation");
+                            let mut clip = skinned.player.clip;
+                            let selected = skinned
+                                .clips
+                                .get(clip)
+                                .map_or("none", |clip| clip.name.as_str());
+                            egui::ComboBox::from_id_source("animation")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    for (i, clip_data) in skinned.clips.iter().enumerate() {
+                                        ui.selectable_value(&mut clip, i, &clip_data.name);
+                                    }
+                                });
+                            skinned.player.play(clip, 0.3);
+                            ui.end_row();
+
+                            ui.label("Animation speed");
+                            ui.add(egui::Slider::new(&mut skinned.player.speed, 0.0..=3.0));
+                            ui.end_row();
+                        }
+
+                        if let Some(emitter) = emitter {
+                            // Up to the rate the particles were allocated for.
+                            ui.label("Sparks per second");
+                            ui.add(egui::Slider::new(&mut emitter.spawn_rate, 0.0..=400.0));
+                            ui.end_row();
+
+                            ui.label("Spark speed");
+                            ui.add(egui::Slider::new(&mut emitter.speed, 0.0..=12.0));
+                            ui.end_row();
+                        }
+
+                        ui.label("LOD hysteresis");
+                        ui.add(egui::Slider::new(
+                            &mut lod_field.options.hysteresis,
+                            0.0..=0.5,
+                        ));
+                        ui.end_row();
+
+                        ui.label("Tori per LOD");
+                        let counts = lod_field
+                            .ranges()
+                            .iter()
+                            .map(|range| range.len().to_string())
+                            .collect::<Vec<_>>();
+                        ui.label(counts.join(" / "));
+                        ui.end_row();
+                    });
+                });
+        });
+
+        let material = self.assets.materials.get_mut(&self.debug_material);
+        if let (Some(opacity), Some(material)) = (opacity, material) {
+            if opacity != material.uniform.opacity {
+                material.set_opacity(&self.queue, opacity);
+            }
+        }
+        if spacing != self.instance_spacing {
+            self.instance_spacing = spacing;
+            self.instances = create_instances(spacing);
+            let instance_data = self
+                .instances
+                .iter()
+                .map(Instance::to_raw)
+                .collect::<Vec<_>>();
+            self.queue.write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(&instance_data),
+            );
+        }
     }
 
     fn create_screenshot(
@@ -499,32 +1231,6 @@ impl State {
             texture_extent,
         );
 
-        let bg_layout = self
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    // View
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    // Sampler
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: None,
-            });
-
         let new_texture_view = new_texture.create_view(&wgpu::TextureViewDescriptor::default());
         let new_sampler = self.device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -535,23 +1241,16 @@ impl State {
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
-        let bg_new_texture = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bg_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&new_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&new_sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+        let handle = self.assets.textures.add(
+            "loop",
+            texture::Texture {
+                texture: new_texture,
+                view: new_texture_view,
+                sampler: new_sampler,
+            },
+        );
         self.texture_bind_group
-            .groups
-            .insert("loop".to_string(), bg_new_texture);
+            .add(&self.device, &self.assets.textures, &handle);
 
         // Submit to gpu command queue.
         let command_buffer = encoder.finish();
@@ -568,6 +1267,105 @@ impl State {
     }
 }
 
+/// What `State::draw_models` draws a mesh with: `material` and `light` in
+/// place of a material and the light, and the pipeline for `alpha_mode` out
+/// of `pipelines`.
+struct ModelStyle<'a> {
+    pipelines: &'a [wgpu::RenderPipeline],
+    material: &'a wgpu::BindGroup,
+    light: &'a wgpu::BindGroup,
+    alpha_mode: AlphaMode,
+}
+
+impl<'a> ModelStyle<'a> {
+    /// Set up `render_pass` for `style` if it's drawn in the pass that
+    /// `blended` stands for.
+    fn set(style: Option<Self>, render_pass: &mut wgpu::RenderPass<'a>, blended: bool) -> bool {
+        let Some(style) = style else {
+            return false;
+        };
+        if (style.alpha_mode == AlphaMode::Blend) != blended {
+            return false;
+        }
+        render_pass.set_pipeline(&style.pipelines[style.alpha_mode as usize]);
+        render_pass.set_bind_group(0, style.material, &[]);
+        render_pass.set_bind_group(3, style.light, &[]);
+        true
+    }
+}
+
+/// `KeyState::alpha_mode` for the HUD and the GUI.
+fn alpha_mode_label(alpha_mode: Option<AlphaMode>) -> String {
+    match alpha_mode {
+        Some(alpha_mode) => format!("{:?}", alpha_mode),
+        None => "per material".to_string(),
+    }
+}
+
+/// `shader` once per `AlphaMode`, in the order of `AlphaMode::ALL`, each
+/// under its own label so that they can be reflected together.
+fn alpha_variants(
+    labels: [&'static str; 3],
+    shader: impl Fn(&'static str) -> Shader<'static>,
+) -> [Shader<'static>; 3] {
+    std::array::from_fn(|i| AlphaMode::ALL[i].define(shader(labels[i])))
+}
+
+/// A model pipeline for each of the `alpha_variants` of a shader.
+fn create_alpha_pipelines(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    reflection: &Reflection,
+    shaders: &[Shader<'static>; 3],
+) -> anyhow::Result<Vec<wgpu::RenderPipeline>> {
+    AlphaMode::ALL
+        .iter()
+        .zip(shaders)
+        .map(|(mode, shader)| {
+            Ok(render::create_render_pipeline(
+                device,
+                &reflection.create_pipeline_layout(device, shader, shader.label())?,
+                config.format,
+                Some(texture::Texture::DEPTH_FORMAT),
+                &[model::ModelVertex::desc(), InstanceRaw::desc()],
+                shader,
+                &mode.pipeline_options(),
+                shader.label(),
+            ))
+        })
+        .collect()
+}
+
+/// The grid of cubes, `spacing` apart.
+fn create_instances(spacing: f32) -> Vec<Instance> {
+    (0..NUM_INSTANCES_PER_ROW)
+        .flat_map(|z| {
+            (0..NUM_INSTANCES_PER_ROW).map(move |x| {
+                let x = spacing * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+                let z = spacing * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+                let position = cgmath::Vector3 { x, y: 0.0, z };
+
+                let rotation = if position.is_zero() {
+                    // this is needed so an object at (0, 0, 0) won't get scaled to zero
+                    // as Quaternions can effect scale if they're not created correctly
+                    cgmath::Quaternion::from_axis_angle(
+                        cgmath::Vector3::unit_z(),
+                        cgmath::Deg(0.0),
+                    )
+                } else {
+                    cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
+                };
+                /*let rotation = cgmath::Quaternion::from_axis_angle(
+                    (0.0, 1.0, 0.0).into(),
+                    cgmath::Deg(180.0),
+                );*/
+
+                Instance { position, rotation }
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
 async fn create_png(
     png_output_path: &str,
     device: &wgpu::Device,
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use cgmath::{prelude::*, Vector3};
+
+use crate::model::MeshData;
+
+/// Each corner of each triangle, as MikkTSpace wants them.
+struct Corners<'a> {
+    data: &'a MeshData,
+    tangents: Vec<[f32; 4]>,
+}
+
+impl Corners<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &crate::model::ModelVertex {
+        &self.data.vertices[self.data.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl mikktspace::Geometry for Corners<'_> {
+    fn num_faces(&self) -> usize {
+        self.data.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        // MikkTSpace puts `v` = 0 at the bottom like OpenGL, and wgpu and
+        // glTF put it at the top.
+        let [u, v] = self.vertex(face, vert).tex_coords;
+        [u, 1.0 - v]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent;
+    }
+}
+
+/// `data` with MikkTSpace tangents, as baked into normal maps by Blender,
+/// Substance and most other tools. A vertex shared by triangles that
+/// disagree on its tangent, as along a mirrored UV seam, is split in two,
+/// so the vertex of `data` each returned vertex came from is returned too.
+pub fn generate(data: &MeshData) -> (MeshData, Vec<u32>) {
+    let mut corners = Corners {
+        data,
+        tangents: vec![[0.0; 4]; data.indices.len()],
+    };
+    if !mikktspace::generate_tangents(&mut corners) {
+        log::warn!("couldn't generate tangents");
+        return (data.clone(), (0..data.vertices.len() as u32).collect());
+    }
+
+    let mut result = MeshData::default();
+    let mut origins = Vec::new();
+    let mut welded = HashMap::new();
+    for (&index, tangent) in data.indices.iter().zip(corners.tangents) {
+        let mut vertex = data.vertices[index as usize];
+        vertex.tangent = fallback(vertex.normal.into(), tangent);
+        let key = (index, vertex.tangent.map(f32::to_bits));
+        let new_index = *welded.entry(key).or_insert_with(|| {
+            result.vertices.push(vertex);
+            origins.push(index);
+            result.vertices.len() as u32 - 1
+        });
+        result.indices.push(new_index);
+    }
+    (result, origins)
+}
+
+/// Any tangent perpendicular to `normal` where MikkTSpace found none, such as
+/// on triangles with no area in texture space.
+fn fallback(normal: Vector3<f32>, tangent: [f32; 4]) -> [f32; 4] {
+    let xyz = Vector3::new(tangent[0], tangent[1], tangent[2]);
+    if xyz.magnitude2() > 1e-12 && xyz.magnitude2().is_finite() {
+        return tangent;
+    }
+    let axis = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let xyz = (axis - normal * normal.dot(axis)).normalize();
+    [xyz.x, xyz.y, xyz.z, 1.0]
+}
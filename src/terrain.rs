@@ -0,0 +1,262 @@
+use std::f32::consts::PI;
+
+use cgmath::Vector3;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    model::{self, AlphaMode, DrawModel, MeshData, Vertex},
+    primitives,
+    reflect::Reflection,
+    render, resources,
+    shader::Shader,
+    texture,
+    vertex::{Instance, InstanceRaw, RotationBundle},
+};
+
+/// Heights from 0 to 1, read from a grayscale elevation map.
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn from_image(img: &image::DynamicImage) -> Self {
+        let luma = img.to_luma16();
+        Self {
+            width: luma.width(),
+            height: luma.height(),
+            heights: luma
+                .pixels()
+                .map(|pixel| pixel[0] as f32 / u16::MAX as f32)
+                .collect(),
+        }
+    }
+
+    /// Bilinearly filtered at texture coordinates. `u` wraps around like the
+    /// longitude of an equirectangular map, `v` is clamped.
+    pub fn sample(&self, u: f32, v: f32) -> f32 {
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let texel = |x: f32, y: f32| {
+            let x = (x as i64).rem_euclid(self.width as i64) as u32;
+            let y = (y as u32).min(self.height - 1);
+            self.heights[(y * self.width + x) as usize]
+        };
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let top = lerp(texel(x0, y0), texel(x0 + 1.0, y0), x - x0);
+        let bottom = lerp(texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0), x - x0);
+        lerp(top, bottom, y - y0)
+    }
+
+    /// The heights colored by `gradient`, which spans 0 to 1, as a texture
+    /// that lines up with the terrain.
+    pub fn colorize(&self, gradient: &colorgrad::Gradient) -> image::RgbaImage {
+        let lut = (0..=255)
+            .map(|i| image::Rgba(gradient.at(i as f64 / 255.0).to_rgba8()))
+            .collect::<Vec<_>>();
+        image::RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let height = self.heights[(y * self.width + x) as usize];
+            lut[(height * 255.0).round() as usize]
+        })
+    }
+
+    /// Distance between texels in texture coordinates.
+    fn texel_size(&self) -> [f32; 2] {
+        [1.0 / self.width as f32, 1.0 / self.height as f32]
+    }
+}
+
+/// Blue seas, green lowlands, brown hills and white peaks.
+pub fn elevation_gradient() -> anyhow::Result<colorgrad::Gradient> {
+    Ok(colorgrad::CustomGradient::new()
+        .colors(&[
+            colorgrad::Color::new(0.05, 0.15, 0.45, 1.0),
+            colorgrad::Color::new(0.15, 0.45, 0.2, 1.0),
+            colorgrad::Color::new(0.45, 0.35, 0.2, 1.0),
+            colorgrad::Color::new(0.5, 0.5, 0.5, 1.0),
+            colorgrad::Color::new(1.0, 1.0, 1.0, 1.0),
+        ])
+        .domain(&[0.0, 0.01, 0.25, 0.5, 0.8])
+        .build()?)
+}
+
+/// A flat terrain centered on the origin, with the whole heightmap spread
+/// over its tiles.
+#[derive(Copy, Clone, Debug)]
+pub struct TerrainOptions {
+    /// Height of the brightest texel, in world units.
+    pub vertical_scale: f32,
+    /// Width and depth of a tile, in world units.
+    pub tile_size: f32,
+    /// Tiles along x and z.
+    pub tiles: [u32; 2],
+    /// Quads along each side of a tile.
+    pub tile_resolution: u32,
+}
+
+/// A sphere displaced along its normals, with the heightmap wrapped around
+/// it as an equirectangular map like `earth.obj`'s texture coordinates.
+#[derive(Copy, Clone, Debug)]
+pub struct SphereOptions {
+    /// Radius at height 0.
+    pub radius: f32,
+    /// Displacement of the brightest texel, in world units.
+    pub vertical_scale: f32,
+    /// Quads around the equator, and half as many from pole to pole.
+    pub segments: u32,
+}
+
+/// Each tile of a flat terrain, in rows along +z.
+pub fn tiles(heightmap: &Heightmap, options: &TerrainOptions) -> Vec<MeshData> {
+    let [tiles_x, tiles_z] = options.tiles;
+    let surface = |u: f32, v: f32| {
+        Vector3::new(
+            (u - 0.5) * tiles_x as f32 * options.tile_size,
+            heightmap.sample(u, v) * options.vertical_scale,
+            (v - 0.5) * tiles_z as f32 * options.tile_size,
+        )
+    };
+    (0..tiles_z)
+        .flat_map(|z| (0..tiles_x).map(move |x| (x, z)))
+        .map(|(x, z)| {
+            let u = x as f32 / tiles_x as f32..(x + 1) as f32 / tiles_x as f32;
+            let v = z as f32 / tiles_z as f32..(z + 1) as f32 / tiles_z as f32;
+            primitives::parametric(
+                surface,
+                u,
+                v,
+                [options.tile_resolution; 2],
+                heightmap.texel_size(),
+            )
+        })
+        .collect()
+}
+
+/// A displaced sphere centered on the origin.
+pub fn sphere(heightmap: &Heightmap, options: &SphereOptions) -> MeshData {
+    let surface = |u: f32, v: f32| {
+        let theta = PI * v;
+        let direction = primitives::around(u) * theta.sin() + Vector3::unit_y() * theta.cos();
+        direction * (options.radius + heightmap.sample(u, v) * options.vertical_scale)
+    };
+    primitives::parametric(
+        surface,
+        0.0..1.0,
+        0.0..1.0,
+        [options.segments, options.segments / 2],
+        heightmap.texel_size(),
+    )
+}
+
+/// A flat terrain and a planet made from the same heightmap, colored by
+/// height and drawn with the material shader.
+pub struct TerrainBundle {
+    pub ground: model::Model,
+    pub planet: model::Model,
+    render_pipeline: wgpu::RenderPipeline,
+    /// The ground's placement, then the planet's.
+    instance_buffer: wgpu::Buffer,
+    /// Left at the identity, so that the terrain doesn't turn with the cubes.
+    rotation_bundle: RotationBundle,
+}
+
+impl TerrainBundle {
+    /// `shader` is the opaque material shader, already reflected.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        reflection: &Reflection,
+        material_layout: &wgpu::BindGroupLayout,
+        shader: &Shader<'_>,
+        file_name: &str,
+        ground: (Instance, &TerrainOptions),
+        planet: (Instance, &SphereOptions),
+    ) -> anyhow::Result<Self> {
+        let heightmap = Heightmap::from_image(&image::load_from_memory(
+            &resources::load_binary(file_name).await?,
+        )?);
+        let colors = image::DynamicImage::ImageRgba8(heightmap.colorize(&elevation_gradient()?));
+        let material = |name: &str| -> anyhow::Result<model::Material> {
+            Ok(model::Material::new(
+                device,
+                name,
+                texture::Texture::from_image(device, queue, &colors, Some(name), false)?,
+                resources::flat_normal_texture(device, queue, name)?,
+                AlphaMode::Opaque,
+                1.0,
+                material_layout,
+            ))
+        };
+
+        let (ground_instance, ground_options) = ground;
+        let ground = model::Model {
+            meshes: tiles(&heightmap, ground_options)
+                .iter()
+                .enumerate()
+                .map(|(i, tile)| tile.create_mesh(device, &format!("terrain tile {}", i), 0))
+                .collect(),
+            materials: vec![material("terrain")?],
+        };
+        let (planet_instance, planet_options) = planet;
+        let planet = model::Model {
+            meshes: vec![sphere(&heightmap, planet_options).create_mesh(device, "planet", 0)],
+            materials: vec![material("planet")?],
+        };
+
+        let render_pipeline = render::create_render_pipeline(
+            device,
+            &reflection.create_pipeline_layout(device, shader, Some("Terrain Pipeline Layout"))?,
+            config.format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &[model::ModelVertex::desc(), InstanceRaw::desc()],
+            shader,
+            &render::PipelineOptions::default(),
+            Some("Terrain Render Pipeline"),
+        );
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Terrain Instance Buffer"),
+            contents: bytemuck::cast_slice(&[ground_instance.to_raw(), planet_instance.to_raw()]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let rotation_bundle = RotationBundle::new(
+            device,
+            &reflection.create_bind_group_layout(device, &["rotation"], None)?,
+        );
+
+        Ok(Self {
+            ground,
+            planet,
+            render_pipeline,
+            instance_buffer,
+            rotation_bundle,
+        })
+    }
+
+    /// Draw with the camera at group 1 and the image based lighting at
+    /// group 3.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        ibl_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.rotation_bundle.bind_group, &[]);
+        render_pass.set_bind_group(3, ibl_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        for (model, instance) in [(&self.ground, 0), (&self.planet, 1)] {
+            render_pass.set_bind_group(0, &model.materials[0].bind_group, &[]);
+            render_pass.draw_model_instanced(
+                model,
+                instance..instance + 1,
+                camera_bind_group,
+                ibl_bind_group,
+            );
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont};
+use wgpu::util::DeviceExt;
+
+use crate::{reflect::Reflection, render, shader::Shader, texture::Texture};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TextUniform {
+    screen_size: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TextVertex {
+    pub position: [f32; 2],
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl TextVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Where a glyph sits in the atlas and how to place it, in pixels relative to
+/// the pen at the top of the line.
+#[derive(Copy, Clone, Debug)]
+struct Glyph {
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    offset: [f32; 2],
+    size: [f32; 2],
+    advance: f32,
+}
+
+/// Printable ASCII rasterized once into a single-channel coverage texture.
+pub struct GlyphAtlas {
+    pub texture: Texture,
+    glyphs: HashMap<char, Glyph>,
+    pub line_height: f32,
+    /// A fully covered texel, for drawing solid rectangles.
+    solid: [f32; 2],
+}
+
+impl GlyphAtlas {
+    const WIDTH: u32 = 512;
+    /// Space between glyphs so that linear filtering doesn't bleed.
+    const PADDING: u32 = 1;
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        font_data: Vec<u8>,
+        pixel_size: f32,
+    ) -> anyhow::Result<Self> {
+        let font = FontArc::try_from_vec(font_data)?;
+        let font = font.as_scaled(PxScale::from(pixel_size));
+        let line_height = font.height().ceil();
+        let row_height = line_height as u32 + Self::PADDING;
+
+        // Rasterize first, then size the texture to fit.
+        let mut rows: Vec<Vec<u8>> = Vec::new();
+        let mut placed = Vec::new();
+        let (mut x, mut y) = (4, 0);
+        for c in ' '..='~' {
+            let mut glyph = font.scaled_glyph(c);
+            glyph.position = ab_glyph::point(0.0, font.ascent());
+            let advance = font.h_advance(glyph.id);
+            let Some(outline) = font.outline_glyph(glyph) else {
+                placed.push((c, None, advance));
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            let (w, h) = (bounds.width() as u32, bounds.height() as u32);
+            if x + w > Self::WIDTH {
+                x = 0;
+                y += row_height;
+            }
+            while rows.len() < (y + row_height) as usize {
+                rows.push(vec![0; Self::WIDTH as usize]);
+            }
+            outline.draw(|gx, gy, coverage| {
+                rows[(y + gy) as usize][(x + gx) as usize] = (coverage * 255.0) as u8;
+            });
+            placed.push((c, Some((x, y, w, h, bounds.min)), advance));
+            x += w + Self::PADDING;
+        }
+        // The solid block in the top left corner.
+        for row in rows.iter_mut().take(2) {
+            row[..2].fill(255);
+        }
+
+        let height = rows.len() as u32;
+        let (width_f, height_f) = (Self::WIDTH as f32, height as f32);
+        let glyphs = placed
+            .into_iter()
+            .map(|(c, rect, advance)| {
+                let glyph = match rect {
+                    Some((x, y, w, h, min)) => Glyph {
+                        uv_min: [x as f32 / width_f, y as f32 / height_f],
+                        uv_max: [(x + w) as f32 / width_f, (y + h) as f32 / height_f],
+                        offset: [min.x, min.y],
+                        size: [w as f32, h as f32],
+                        advance,
+                    },
+                    None => Glyph {
+                        uv_min: [0.0; 2],
+                        uv_max: [0.0; 2],
+                        offset: [0.0; 2],
+                        size: [0.0; 2],
+                        advance,
+                    },
+                };
+                (c, glyph)
+            })
+            .collect();
+
+        let size = wgpu::Extent3d {
+            width: Self::WIDTH,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glyph_atlas"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &rows.concat(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(Self::WIDTH),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            texture: Texture {
+                texture,
+                view,
+                sampler,
+            },
+            glyphs,
+            line_height,
+            solid: [1.0 / width_f, 1.0 / height_f],
+        })
+    }
+}
+
+/// Screen-space text and rectangles, queued during a frame and drawn over
+/// everything else by `render`.
+pub struct TextRenderer {
+    atlas: GlyphAtlas,
+    uniform: TextUniform,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    vertices: Vec<TextVertex>,
+    buffer: wgpu::Buffer,
+    /// Size of `buffer` in vertices.
+    capacity: usize,
+}
+
+impl TextRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        atlas: GlyphAtlas,
+    ) -> anyhow::Result<Self> {
+        let shader = Shader::new(Some("text.shader"), include_str!("text.wgsl"));
+        let reflection = Reflection::new(&[&shader])?;
+        reflection.check_sizes(&[("text", std::mem::size_of::<TextUniform>())])?;
+
+        let uniform = TextUniform {
+            screen_size: [config.width as f32, config.height as f32],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("text.uniform_buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let layout = reflection.create_bind_group_layout(
+            device,
+            &["text", "t_atlas", "s_atlas"],
+            Some("text.bind_group_layout"),
+        )?;
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&atlas.texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&atlas.texture.sampler),
+                },
+            ],
+            label: Some("text.bind_group"),
+        });
+
+        let render_pipeline = render::create_render_pipeline(
+            device,
+            &reflection.create_pipeline_layout(device, &shader, Some("text.pipeline_layout"))?,
+            config.format,
+            None,
+            &[TextVertex::desc()],
+            &shader,
+            &render::PipelineOptions {
+                cull_mode: None,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                ..Default::default()
+            },
+            Some("text.render_pipeline"),
+        );
+
+        let capacity = 4096;
+        Ok(Self {
+            atlas,
+            uniform,
+            uniform_buffer,
+            bind_group,
+            render_pipeline,
+            vertices: Vec::new(),
+            buffer: create_buffer(device, capacity),
+            capacity,
+        })
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, config: &wgpu::SurfaceConfiguration) {
+        self.uniform.screen_size = [config.width as f32, config.height as f32];
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    /// Width and height of `text` in pixels.
+    pub fn measure(&self, text: &str) -> [f32; 2] {
+        let width = text
+            .lines()
+            .map(|line| {
+                line.chars()
+                    .filter_map(|c| self.glyph(c))
+                    .map(|g| g.advance)
+                    .sum()
+            })
+            .fold(0.0, f32::max);
+        [width, text.lines().count() as f32 * self.atlas.line_height]
+    }
+
+    /// Queue `text` with its top left corner at `position` in pixels.
+    pub fn text(&mut self, text: &str, position: [f32; 2], color: [f32; 4]) {
+        let mut pen = position;
+        for c in text.chars() {
+            if c == '\n' {
+                pen = [position[0], pen[1] + self.atlas.line_height];
+                continue;
+            }
+            let Some(glyph) = self.glyph(c) else {
+                continue;
+            };
+            if glyph.size[0] > 0.0 {
+                let min = [pen[0] + glyph.offset[0], pen[1] + glyph.offset[1]];
+                let max = [min[0] + glyph.size[0], min[1] + glyph.size[1]];
+                self.quad(min, max, glyph.uv_min, glyph.uv_max, color);
+            }
+            pen[0] += glyph.advance;
+        }
+    }
+
+    /// Queue a solid rectangle, for example as a backdrop for text.
+    pub fn rect(&mut self, min: [f32; 2], max: [f32; 2], color: [f32; 4]) {
+        let uv = self.atlas.solid;
+        self.quad(min, max, uv, uv, color);
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        self.atlas
+            .glyphs
+            .get(&c)
+            .or_else(|| self.atlas.glyphs.get(&'?'))
+            .copied()
+    }
+
+    fn quad(
+        &mut self,
+        min: [f32; 2],
+        max: [f32; 2],
+        uv_min: [f32; 2],
+        uv_max: [f32; 2],
+        color: [f32; 4],
+    ) {
+        let vertex = |x: usize, y: usize| TextVertex {
+            position: [[min[0], max[0]][x], [min[1], max[1]][y]],
+            tex_coords: [[uv_min[0], uv_max[0]][x], [uv_min[1], uv_max[1]][y]],
+            color,
+        };
+        self.vertices.extend([
+            vertex(0, 0),
+            vertex(0, 1),
+            vertex(1, 1),
+            vertex(0, 0),
+            vertex(1, 1),
+            vertex(1, 0),
+        ]);
+    }
+
+    /// Draw and clear everything queued since the last call.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.buffer = create_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("text.render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.draw(0..self.vertices.len() as u32, 0..1);
+        drop(render_pass);
+
+        self.vertices.clear();
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("text.buffer"),
+        size: (capacity * std::mem::size_of::<TextVertex>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
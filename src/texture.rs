@@ -3,7 +3,10 @@ use std::collections::HashMap;
 use anyhow::*;
 use image::GenericImageView;
 
-use crate::resources;
+use crate::{
+    assets::{AssetServer, Assets, Handle, LoadState},
+    resources,
+};
 
 /// Store an image on the gpu to use as a texture.
 pub struct Texture {
@@ -158,64 +161,212 @@ impl Texture {
             sampler,
         })
     }
-}
 
-pub struct TextureBindGroup {
-    pub layout: wgpu::BindGroupLayout,
-    pub groups: HashMap<String, wgpu::BindGroup>,
-}
+    /// Create a cubemap from six faces in +X, -X, +Y, -Y, +Z, -Z order.
+    pub fn create_cubemap(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::RgbaImage],
+        label: Option<&str>,
+    ) -> Result<Self> {
+        if faces.len() != 6 {
+            bail!("a cubemap needs 6 faces, got {}", faces.len());
+        }
+        let (width, height) = faces[0].dimensions();
+        if width != height || faces.iter().any(|face| face.dimensions() != (width, height)) {
+            bail!("cubemap faces must be square and all the same size");
+        }
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 6,
+        };
 
-impl TextureBindGroup {
-    pub fn new(device: &wgpu::Device, _queue: &wgpu::Queue, label: Option<&str>) -> Self {
-        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                // View
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        for (layer, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
                     },
-                    count: None,
                 },
-                // Sampler
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
+                face,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * width),
+                    rows_per_image: std::num::NonZeroU32::new(height),
                 },
-            ],
-            label,
+                wgpu::Extent3d {
+                    depth_or_array_layers: 1,
+                    ..size
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
         });
 
-        TextureBindGroup {
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+}
+
+/// Resample an equirectangular panorama into six cubemap faces of `size`
+/// pixels, in the same order as `Texture::create_cubemap`.
+pub fn equirectangular_to_cube_faces(img: &image::DynamicImage, size: u32) -> Vec<image::RgbaImage> {
+    let rgba = img.to_rgba8();
+    (0..6)
+        .map(|face| {
+            image::RgbaImage::from_fn(size, size, |x, y| {
+                // Face coordinates in [-1, 1], with t pointing down.
+                let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+                let dir = cube_face_direction(face, s, t);
+                sample_equirectangular(&rgba, dir)
+            })
+        })
+        .collect()
+}
+
+pub fn cube_face_direction(face: usize, s: f32, t: f32) -> cgmath::Vector3<f32> {
+    use cgmath::InnerSpace;
+    let dir = match face {
+        0 => cgmath::vec3(1.0, -t, -s),
+        1 => cgmath::vec3(-1.0, -t, s),
+        2 => cgmath::vec3(s, 1.0, t),
+        3 => cgmath::vec3(s, -1.0, -t),
+        4 => cgmath::vec3(s, -t, 1.0),
+        _ => cgmath::vec3(-s, -t, -1.0),
+    };
+    dir.normalize()
+}
+
+/// Bilinearly sample a panorama in the direction `dir`.
+fn sample_equirectangular(img: &image::RgbaImage, dir: cgmath::Vector3<f32>) -> image::Rgba<u8> {
+    use std::f32::consts::PI;
+    let (width, height) = img.dimensions();
+    let u = 0.5 + dir.x.atan2(-dir.z) / (2.0 * PI);
+    let v = dir.y.clamp(-1.0, 1.0).acos() / PI;
+
+    let fx = u * width as f32 - 0.5;
+    let fy = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (fx.floor(), fy.floor());
+    let (ax, ay) = (fx - x0, fy - y0);
+    let x0 = x0.rem_euclid(width as f32) as u32;
+    let x1 = (x0 + 1) % width;
+    let y0 = y0 as u32;
+    let y1 = (y0 + 1).min(height - 1);
+
+    let mut out = [0u8; 4];
+    for (c, channel) in out.iter_mut().enumerate() {
+        let p = |x, y| img.get_pixel(x, y)[c] as f32;
+        let top = p(x0, y0) * (1.0 - ax) + p(x1, y0) * ax;
+        let bottom = p(x0, y1) * (1.0 - ax) + p(x1, y1) * ax;
+        *channel = (top * (1.0 - ay) + bottom * ay).round() as u8;
+    }
+    image::Rgba(out)
+}
+
+/// A bind group for each texture of a set, for switching between them.
+pub struct TextureBindGroup {
+    pub layout: wgpu::BindGroupLayout,
+    pub groups: HashMap<Handle<Texture>, wgpu::BindGroup>,
+    /// Added while still loading, waiting for `update`.
+    pending: Vec<Handle<Texture>>,
+    /// Bound in place of textures that are still loading or that failed to.
+    pub placeholder: wgpu::BindGroup,
+}
+
+impl TextureBindGroup {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let placeholder = resources::solid_color_texture(device, queue, [0.5; 4], "placeholder")?
+            .create_bind_group(device, &layout, Some("placeholder"));
+        Ok(TextureBindGroup {
             layout,
             groups: HashMap::new(),
-        }
+            pending: Vec::new(),
+            placeholder,
+        })
     }
 
-    pub async fn from_files(
+    /// Start loading `filenames` through `assets`, returning their handles
+    /// in order.
+    pub fn from_files(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        filenames: Vec<&str>,
-    ) -> anyhow::Result<Self> {
-        let mut group = TextureBindGroup::new(device, queue, Some("from_files"));
+        layout: wgpu::BindGroupLayout,
+        assets: &mut AssetServer,
+        filenames: &[&str],
+    ) -> Result<(Self, Vec<Handle<Texture>>)> {
+        let mut group = TextureBindGroup::new(device, queue, layout)?;
+        let mut handles = Vec::new();
         for filename in filenames {
-            let texture = resources::load_texture(filename, device, queue).await?;
-            group.add(device, texture, filename);
+            let handle = assets.request_texture(filename, false);
+            group.add(device, &assets.textures, &handle);
+            handles.push(handle);
         }
-        Ok(group)
+        Ok((group, handles))
     }
 
-    pub fn add(&mut self, device: &wgpu::Device, texture: Texture, label: &str) {
-        let bind_group = texture.create_bind_group(device, &self.layout, Some(label));
-        self.groups.insert(label.to_string(), bind_group);
+    /// Make the bind group for `handle`, now if it has loaded or in `update`
+    /// once it does.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        textures: &Assets<Texture>,
+        handle: &Handle<Texture>,
+    ) {
+        if let Some(texture) = textures.get(handle) {
+            let label = textures.path(handle);
+            let bind_group = texture.create_bind_group(device, &self.layout, Some(label));
+            self.groups.insert(handle.clone(), bind_group);
+        } else if *textures.state(handle) == LoadState::Pending {
+            self.pending.push(handle.clone());
+        }
+    }
+
+    /// Make the bind groups of the textures that have finished loading.
+    pub fn update(&mut self, device: &wgpu::Device, textures: &Assets<Texture>) {
+        for handle in std::mem::take(&mut self.pending) {
+            self.add(device, textures, &handle);
+        }
     }
 
-    pub fn get(&self, label: &str) -> &wgpu::BindGroup {
-        self.groups.get(label).unwrap()
+    /// The placeholder when `handle` hasn't loaded.
+    pub fn get(&self, handle: &Handle<Texture>) -> &wgpu::BindGroup {
+        self.groups.get(handle).unwrap_or(&self.placeholder)
     }
 }
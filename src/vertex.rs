@@ -1,5 +1,5 @@
 use wgpu::util::DeviceExt;
-use crate::{model, vertex};
+use crate::model;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -25,12 +25,11 @@ pub struct RotationBundle {
     pub angle: cgmath::Rad<f32>,
     pub uniform: RotationUniform,
     pub buffer: wgpu::Buffer,
-    pub layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 }
 
 impl RotationBundle {
-    pub fn new(device: &wgpu::Device) -> Self {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
         let mut uniform = RotationUniform::new();
         let angle = cgmath::Rad(0.0); 
         uniform.update_angle(angle);
@@ -39,22 +38,8 @@ impl RotationBundle {
              contents: bytemuck::cast_slice(&[uniform]),
              usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
          });
-         let layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("rotation_bind_group_layout"),
-            });
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &layout,
+            layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
                 resource: buffer.as_entire_binding(),
@@ -66,7 +51,6 @@ impl RotationBundle {
             angle,
             uniform,
             buffer,
-            layout,
             bind_group,
         }
     }
@@ -78,81 +62,6 @@ impl RotationBundle {
     }
 }
 
-#[repr(C)]
-#[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct GradientUniform {
-    color: [f32; 4],
-}
-
-pub struct GradientSource {
-    pub uniform: GradientUniform,
-    gradient: colorgrad::Gradient,
-    index: f64,
-    pub layout: wgpu::BindGroupLayout,
-    pub bind_group: wgpu::BindGroup,
-    pub gradient_buffer: wgpu::Buffer,
-}
-
-impl GradientSource {
-    #[allow(clippy::new_without_default)]
-    pub fn new(device: &wgpu::Device) -> Self {
-
-        let uniform = vertex::GradientUniform::default();
-        let gradient_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Gradient Buffer"),
-            contents: bytemuck::cast_slice(&[uniform]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-        let layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("gradient_bind_group_layout"),
-            });
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: gradient_buffer.as_entire_binding(),
-            }],
-            label: Some("gradient_bind_group"),
-        });
-
-        Self {
-            uniform,
-            gradient: colorgrad::rainbow(),
-            index: 0.0,
-            bind_group,
-            layout,
-            gradient_buffer,
-        }
-    }
-
-    pub fn update(&mut self, queue: &wgpu::Queue) {
-        let color = self.gradient.at(self.index);
-        self.uniform.color = [
-            color.r as f32,
-            color.g as f32,
-            color.b as f32,
-            color.a as f32,
-        ];
-        self.index = (self.index + 0.01) % 1.0;
-        queue.write_buffer(
-            &self.gradient_buffer,
-            0,
-            bytemuck::cast_slice(&[self.uniform]),
-        );
-    }
-}
-
 pub struct Instance {
     pub position: cgmath::Vector3<f32>,
     pub rotation: cgmath::Quaternion<f32>,
@@ -0,0 +1,338 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{bail, ensure, Context};
+
+/// What's mounted at a prefix of the asset paths.
+#[derive(Clone)]
+pub enum Mount {
+    /// Another directory of assets, loaded like any other path.
+    Dir(String),
+    Archive(Arc<Archive>),
+}
+
+/// Newest first, so that later mounts cover earlier ones.
+static MOUNTS: RwLock<Vec<(String, Mount)>> = RwLock::new(Vec::new());
+
+/// Serve paths under `prefix` from `mount`, falling back to whatever was
+/// there before for files it doesn't have.
+pub fn mount(prefix: &str, mount: Mount) {
+    let prefix = normalize(prefix);
+    log::info!("mounted {} at {:?}", mount.name(), prefix);
+    MOUNTS.write().unwrap().insert(0, (prefix, mount));
+}
+
+/// The mounts `file_name` is under, each with the path within it, in the
+/// order to look in them.
+pub fn mounts(file_name: &str) -> Vec<(Mount, String)> {
+    MOUNTS
+        .read()
+        .unwrap()
+        .iter()
+        .filter_map(|(prefix, mount)| {
+            let path = match file_name.strip_prefix(prefix.as_str()) {
+                Some(path) if prefix.is_empty() => path,
+                Some(path) => path.strip_prefix('/')?,
+                None => return None,
+            };
+            Some((mount.clone(), path.to_string()))
+        })
+        .collect()
+}
+
+impl Mount {
+    pub fn name(&self) -> &str {
+        match self {
+            Mount::Dir(dir) => dir,
+            Mount::Archive(archive) => &archive.name,
+        }
+    }
+}
+
+/// `path` with `.` and `..` resolved and `/` as the only separator, for
+/// comparing with mount prefixes and archive entries.
+pub fn normalize(path: &str) -> String {
+    let mut parts = Vec::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// `path` as referenced from within `file_name`, like textures named by an
+/// OBJ's materials.
+pub fn relative_to(file_name: &str, path: &str) -> String {
+    match file_name.rsplit_once('/') {
+        Some((dir, _)) => normalize(&format!("{}/{}", dir, path)),
+        None => normalize(path),
+    }
+}
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+struct Entry {
+    method: u16,
+    crc: u32,
+    size: usize,
+    /// Of the data in the archive, compressed.
+    range: Range<usize>,
+}
+
+/// A zip archive in memory, with stored and deflated files.
+pub struct Archive {
+    name: String,
+    data: Vec<u8>,
+    entries: HashMap<String, Entry>,
+}
+
+impl Archive {
+    pub fn new(name: &str, data: Vec<u8>) -> anyhow::Result<Self> {
+        let entries = read_entries(&data).with_context(|| format!("{} isn't a zip file", name))?;
+        Ok(Self {
+            name: name.to_string(),
+            data,
+            entries,
+        })
+    }
+
+    /// `None` if there's no file at `path`.
+    pub fn read(&self, path: &str) -> Option<anyhow::Result<Vec<u8>>> {
+        let entry = self.entries.get(path)?;
+        Some(
+            self.extract(entry)
+                .with_context(|| format!("couldn't extract {} from {}", path, self.name)),
+        )
+    }
+
+    fn extract(&self, entry: &Entry) -> anyhow::Result<Vec<u8>> {
+        let compressed = &self.data[entry.range.clone()];
+        let data = match entry.method {
+            STORED => compressed.to_vec(),
+            DEFLATED => miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, entry.size)
+                .map_err(|status| anyhow::anyhow!("inflating failed: {:?}", status))?,
+            method => bail!("compression method {} isn't supported", method),
+        };
+        ensure!(data.len() == entry.size, "the size is wrong");
+        ensure!(crc32fast::hash(&data) == entry.crc, "the checksum is wrong");
+        Ok(data)
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let bytes = data.get(offset..offset + 2).context("truncated")?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let bytes = data.get(offset..offset + 4).context("truncated")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// The files listed in the central directory, without directories.
+fn read_entries(data: &[u8]) -> anyhow::Result<HashMap<String, Entry>> {
+    // The end of central directory record is last, followed only by a
+    // comment of up to 64 KiB.
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .take(u16::MAX as usize + 1)
+        .find(|&offset| u32_at(data, offset).ok() == Some(END_OF_CENTRAL_DIRECTORY))
+        .context("no end of central directory")?;
+    let count = u16_at(data, end + 10)?;
+    let mut offset = u32_at(data, end + 16)? as usize;
+
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        ensure!(
+            u32_at(data, offset)? == CENTRAL_HEADER,
+            "bad central directory"
+        );
+        let flags = u16_at(data, offset + 8)?;
+        let method = u16_at(data, offset + 10)?;
+        let crc = u32_at(data, offset + 16)?;
+        let compressed_size = u32_at(data, offset + 20)? as usize;
+        let size = u32_at(data, offset + 24)? as usize;
+        let name_len = u16_at(data, offset + 28)? as usize;
+        let extra_len = u16_at(data, offset + 30)? as usize;
+        let comment_len = u16_at(data, offset + 32)? as usize;
+        let local = u32_at(data, offset + 42)? as usize;
+        let name = data
+            .get(offset + 46..offset + 46 + name_len)
+            .context("truncated")?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        offset += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') {
+            continue;
+        }
+        ensure!(flags & 1 == 0, "{} is encrypted", name);
+        ensure!(
+            compressed_size != u32::MAX as usize && local != u32::MAX as usize,
+            "{} needs zip64, which isn't supported",
+            name
+        );
+        ensure!(
+            u32_at(data, local)? == LOCAL_HEADER,
+            "bad local header for {}",
+            name
+        );
+        // The local header repeats the name, but may have its own extra field.
+        let start =
+            local + 30 + u16_at(data, local + 26)? as usize + u16_at(data, local + 28)? as usize;
+        ensure!(
+            start + compressed_size <= data.len(),
+            "{} is truncated",
+            name
+        );
+        entries.insert(
+            normalize(&name),
+            Entry {
+                method,
+                crc,
+                size,
+                range: start..start + compressed_size,
+            },
+        );
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A zip archive of `(name, data, deflated)` files, ending in `comment`.
+    fn zip(files: &[(&str, &[u8], bool)], comment: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+        for &(name, data, deflated) in files {
+            let (method, stored) = if deflated {
+                (DEFLATED, miniz_oxide::deflate::compress_to_vec(data, 6))
+            } else {
+                (STORED, data.to_vec())
+            };
+            let crc = crc32fast::hash(data);
+            let local = out.len() as u32;
+
+            out.extend(LOCAL_HEADER.to_le_bytes());
+            out.extend([20, 0, 0, 0]);
+            out.extend(method.to_le_bytes());
+            out.extend([0; 4]);
+            out.extend(crc.to_le_bytes());
+            out.extend((stored.len() as u32).to_le_bytes());
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend([0; 2]);
+            out.extend(name.as_bytes());
+            out.extend(&stored);
+
+            central.extend(CENTRAL_HEADER.to_le_bytes());
+            central.extend([20, 0, 20, 0, 0, 0]);
+            central.extend(method.to_le_bytes());
+            central.extend([0; 4]);
+            central.extend(crc.to_le_bytes());
+            central.extend((stored.len() as u32).to_le_bytes());
+            central.extend((data.len() as u32).to_le_bytes());
+            central.extend((name.len() as u16).to_le_bytes());
+            central.extend([0; 12]);
+            central.extend(local.to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+
+        let offset = out.len() as u32;
+        out.extend(&central);
+        out.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        out.extend([0; 4]);
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((central.len() as u32).to_le_bytes());
+        out.extend(offset.to_le_bytes());
+        out.extend((comment.len() as u16).to_le_bytes());
+        out.extend(comment);
+        out
+    }
+
+    fn read(archive: &Archive, path: &str) -> Vec<u8> {
+        archive.read(path).unwrap().unwrap()
+    }
+
+    #[test]
+    fn stored_and_deflated() {
+        let text = b"newmtl Material\nmap_Kd cube-diffuse.jpg\n".repeat(10);
+        let data = zip(
+            &[
+                ("cube.obj", b"o Cube", false),
+                ("sub/cube.mtl", &text, true),
+                ("sub/", b"", false),
+            ],
+            b"",
+        );
+        let archive = Archive::new("test.zip", data).unwrap();
+        assert_eq!(read(&archive, "cube.obj"), b"o Cube");
+        assert_eq!(read(&archive, "sub/cube.mtl"), text);
+        assert!(archive.read("sub/").is_none());
+        assert!(archive.read("missing.png").is_none());
+    }
+
+    #[test]
+    fn comment() {
+        let data = zip(&[("a.txt", b"a", false)], &[b'#'; 1000]);
+        let archive = Archive::new("test.zip", data).unwrap();
+        assert_eq!(read(&archive, "a.txt"), b"a");
+    }
+
+    #[test]
+    fn truncated() {
+        let data = zip(&[("a.txt", b"aaaa", false), ("b.txt", b"bbbb", true)], b"");
+        for len in 0..data.len() {
+            assert!(Archive::new("test.zip", data[..len].to_vec()).is_err());
+        }
+    }
+
+    #[test]
+    fn corrupted() {
+        let data = zip(&[("a.txt", b"aaaa", false), ("b.txt", b"bbbb", true)], b"");
+        // Whatever a flipped byte does, it mustn't panic.
+        for i in 0..data.len() {
+            let mut data = data.clone();
+            data[i] ^= 0xff;
+            if let Ok(archive) = Archive::new("test.zip", data) {
+                for path in ["a.txt", "b.txt"] {
+                    let _ = archive.read(path);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normalized() {
+        assert_eq!(normalize("a/./b/../c"), "a/c");
+        assert_eq!(normalize("/a//b/"), "a/b");
+        assert_eq!(normalize("a\\b\\..\\c"), "a/c");
+        assert_eq!(normalize("../../a"), "a");
+        assert_eq!(normalize("a/.."), "");
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(relative_to("cube.obj", "cube.mtl"), "cube.mtl");
+        assert_eq!(relative_to("pack/cube.obj", "cube.mtl"), "pack/cube.mtl");
+        assert_eq!(
+            relative_to("pack/obj/cube.obj", "../tex/a.png"),
+            "pack/tex/a.png"
+        );
+        assert_eq!(relative_to("pack/cube.obj", "../../a.png"), "a.png");
+    }
+}